@@ -0,0 +1,516 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use super::pc::Instrution;
+use super::reg::RegIndex;
+
+/// RISC-V ABI names of x0 to x31.
+pub const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Most registers an instruction can name.
+const MAX_REGS: usize = u8::MAX as usize + 1;
+
+/// An assembler error. `line` and `column` are 1-based.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Every error found in a program.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|v| writeln!(f, "{}", v))
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    column: usize,
+    text: &'a str,
+}
+
+/// Drop a trailing `#`, `;` or `//` comment.
+fn strip_comment(line: &str) -> &str {
+    let end = [line.find('#'), line.find(';'), line.find("//")]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Split on whitespace and commas. A `label:` glued to the mnemonic is split
+/// off, and `imm(rs)` becomes the two tokens `imm rs`.
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        let sep = c.is_whitespace() || c == ',';
+        match (start, sep) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tokens.push(Token {
+                    column: s + 1,
+                    text: &line[s..i],
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+        .into_iter()
+        .flat_map(|v| {
+            if let Some((label, rest)) = v.text.split_once(':').filter(|v| !v.1.is_empty()) {
+                vec![
+                    Token {
+                        column: v.column,
+                        text: &v.text[..label.len() + 1],
+                    },
+                    Token {
+                        column: v.column + label.len() + 1,
+                        text: rest,
+                    },
+                ]
+            } else if let Some((imm, rest)) = v.text.split_once('(') {
+                let reg = rest.strip_suffix(')').unwrap_or(rest);
+                vec![
+                    Token {
+                        column: v.column,
+                        text: if imm.is_empty() { "0" } else { imm },
+                    },
+                    Token {
+                        column: v.column + imm.len() + 1,
+                        text: reg,
+                    },
+                ]
+            } else {
+                vec![v]
+            }
+        })
+        .collect()
+}
+
+/// The index of a register written as one of `prefixes` and a number.
+fn numbered(text: &str, prefixes: &[char]) -> Option<usize> {
+    let n = text.strip_prefix(prefixes)?;
+    (!n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        .then(|| n.parse::<usize>().unwrap_or(usize::MAX))
+}
+
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+struct Parser<'a> {
+    line: usize,
+    labels: &'a HashMap<&'a str, u32>,
+    regs: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, column: usize, message: String) -> Diagnostic {
+        Diagnostic {
+            line: self.line,
+            column,
+            message,
+        }
+    }
+
+    /// Check a register index against the size of the register file.
+    fn in_range(&self, token: Token<'_>, index: usize) -> Result<RegIndex, Diagnostic> {
+        RegIndex::new(index, self.regs).ok_or_else(|| {
+            self.error(
+                token.column,
+                format!(
+                    "register `{}` is out of range, the machine has {} registers",
+                    token.text, self.regs
+                ),
+            )
+        })
+    }
+
+    /// An integer register, `x5`, `r5` as in the textbooks, or an ABI name.
+    fn reg(&self, token: Token<'_>) -> Result<RegIndex, Diagnostic> {
+        let text = token.text.to_ascii_lowercase();
+        let index = match numbered(&text, &['x', 'r']) {
+            Some(n) => n,
+            None => match text.as_str() {
+                "fp" => 8,
+                name => ABI_NAMES.iter().position(|v| *v == name).ok_or_else(|| {
+                    self.error(
+                        token.column,
+                        format!("expected a register, found `{}`", token.text),
+                    )
+                })?,
+            },
+        };
+        self.in_range(token, index)
+    }
+
+    /// A floating-point register, `f0` to `f31`.
+    fn freg(&self, token: Token<'_>) -> Result<RegIndex, Diagnostic> {
+        let index = numbered(&token.text.to_ascii_lowercase(), &['f']).ok_or_else(|| {
+            self.error(
+                token.column,
+                format!("expected an FP register, found `{}`", token.text),
+            )
+        })?;
+        self.in_range(token, index)
+    }
+
+    fn number(&self, token: Token<'_>) -> Result<i64, Diagnostic> {
+        let (neg, text) = match token.text.strip_prefix('-') {
+            Some(v) => (true, v),
+            None => (false, token.text),
+        };
+        let value = match text.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => text.parse::<i64>(),
+        }
+        .map_err(|_| {
+            self.error(
+                token.column,
+                format!("expected a number, found `{}`", token.text),
+            )
+        })?;
+        Ok(if neg { -value } else { value })
+    }
+
+    fn imm_in(&self, token: Token<'_>, min: i64, max: i64) -> Result<i32, Diagnostic> {
+        let value = self.number(token)?;
+        if value < min || value > max {
+            return Err(self.error(
+                token.column,
+                format!("immediate {} is out of range {}..={}", value, min, max),
+            ));
+        }
+        Ok(value as i32)
+    }
+
+    /// A signed 12-bit immediate.
+    fn imm(&self, token: Token<'_>) -> Result<i32, Diagnostic> {
+        self.imm_in(token, -2048, 2047)
+    }
+
+    /// A shift amount.
+    fn shamt(&self, token: Token<'_>) -> Result<i32, Diagnostic> {
+        self.imm_in(token, 0, 31)
+    }
+
+    /// A 20-bit upper immediate, written signed or unsigned.
+    fn upper(&self, token: Token<'_>) -> Result<i32, Diagnostic> {
+        self.imm_in(token, -(1 << 19), (1 << 20) - 1)
+    }
+
+    fn target(&self, token: Token<'_>) -> Result<u32, Diagnostic> {
+        if let Some(index) = self.labels.get(token.text) {
+            return Ok(*index);
+        }
+        if is_label(token.text) {
+            return Err(self.error(token.column, format!("unknown label `{}`", token.text)));
+        }
+        let value = self.number(token)?;
+        u32::try_from(value).map_err(|_| {
+            self.error(
+                token.column,
+                format!("{} is not an instruction index", value),
+            )
+        })
+    }
+
+    /// Parse a mnemonic and its operands, with any labels already removed.
+    fn instruction(&self, tokens: &[Token<'_>]) -> Result<Instrution, Diagnostic> {
+        let op = tokens[0];
+        let args = &tokens[1..];
+        let mnemonic = op.text.to_ascii_lowercase();
+        let expected = match mnemonic.as_str() {
+            "add" | "sub" | "sll" | "slt" | "sltu" | "xor" | "srl" | "sra" | "or" | "and"
+            | "mul" | "div" | "rem" | "addi" | "slti" | "sltiu" | "xori" | "ori" | "andi"
+            | "slli" | "srli" | "srai" | "lw" | "sw" | "beq" | "bne" | "blt" | "bge" => vec![3],
+            "flw" | "fld" | "l.s" | "l.d" | "fsw" | "fsd" | "s.s" | "s.d" => vec![3],
+            "fadd.s" | "fadd.d" | "add.s" | "add.d" | "fsub.s" | "fsub.d" | "sub.s" | "sub.d"
+            | "fmul.s" | "fmul.d" | "mul.s" | "mul.d" | "fdiv.s" | "fdiv.d" | "div.s" | "div.d" => {
+                vec![3]
+            }
+            "lui" | "auipc" => vec![2],
+            "jal" => vec![1, 2],
+            "jalr" => vec![2, 3],
+            _ => return Err(self.error(op.column, format!("unknown instruction `{}`", op.text))),
+        };
+        if !expected.contains(&args.len()) {
+            return Err(self.error(
+                op.column,
+                format!(
+                    "`{}` expects {} operands, found {}",
+                    mnemonic,
+                    expected
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" or "),
+                    args.len()
+                ),
+            ));
+        }
+        let rrr =
+            |f: fn(RegIndex, RegIndex, RegIndex) -> Instrution| -> Result<Instrution, Diagnostic> {
+                Ok(f(
+                    self.reg(args[0])?,
+                    self.reg(args[1])?,
+                    self.reg(args[2])?,
+                ))
+            };
+        let fff =
+            |f: fn(RegIndex, RegIndex, RegIndex) -> Instrution| -> Result<Instrution, Diagnostic> {
+                Ok(f(
+                    self.freg(args[0])?,
+                    self.freg(args[1])?,
+                    self.freg(args[2])?,
+                ))
+            };
+        let rri = |f: fn(RegIndex, RegIndex, i32) -> Instrution,
+                   shift: bool|
+         -> Result<Instrution, Diagnostic> {
+            let imm = if shift {
+                self.shamt(args[2])?
+            } else {
+                self.imm(args[2])?
+            };
+            Ok(f(self.reg(args[0])?, self.reg(args[1])?, imm))
+        };
+        Ok(match mnemonic.as_str() {
+            "add" => rrr(Instrution::Add)?,
+            "sub" => rrr(Instrution::Sub)?,
+            "sll" => rrr(Instrution::Sll)?,
+            "slt" => rrr(Instrution::Slt)?,
+            "sltu" => rrr(Instrution::Sltu)?,
+            "xor" => rrr(Instrution::Xor)?,
+            "srl" => rrr(Instrution::Srl)?,
+            "sra" => rrr(Instrution::Sra)?,
+            "or" => rrr(Instrution::Or)?,
+            "and" => rrr(Instrution::And)?,
+            "addi" => rri(Instrution::Addi, false)?,
+            "slti" => rri(Instrution::Slti, false)?,
+            "sltiu" => rri(Instrution::Sltiu, false)?,
+            "xori" => rri(Instrution::Xori, false)?,
+            "ori" => rri(Instrution::Ori, false)?,
+            "andi" => rri(Instrution::Andi, false)?,
+            "slli" => rri(Instrution::Slli, true)?,
+            "srli" => rri(Instrution::Srli, true)?,
+            "srai" => rri(Instrution::Srai, true)?,
+            "mul" => rrr(Instrution::Mul)?,
+            "div" => rrr(Instrution::Div)?,
+            "rem" => rrr(Instrution::Rem)?,
+            "lui" => Instrution::Lui(self.reg(args[0])?, self.upper(args[1])?),
+            "auipc" => Instrution::Auipc(self.reg(args[0])?, self.upper(args[1])?),
+            "lw" => Instrution::Lw(self.reg(args[0])?, self.imm(args[1])?, self.reg(args[2])?),
+            "sw" => Instrution::Sw(self.reg(args[0])?, self.imm(args[1])?, self.reg(args[2])?),
+            // the double precision spellings of the textbook examples run
+            // in single precision
+            "flw" | "fld" | "l.s" | "l.d" => {
+                Instrution::Flw(self.freg(args[0])?, self.imm(args[1])?, self.reg(args[2])?)
+            }
+            "fsw" | "fsd" | "s.s" | "s.d" => {
+                Instrution::Fsw(self.freg(args[0])?, self.imm(args[1])?, self.reg(args[2])?)
+            }
+            "fadd.s" | "fadd.d" | "add.s" | "add.d" => fff(Instrution::Fadd)?,
+            "fsub.s" | "fsub.d" | "sub.s" | "sub.d" => fff(Instrution::Fsub)?,
+            "fmul.s" | "fmul.d" | "mul.s" | "mul.d" => fff(Instrution::Fmul)?,
+            "fdiv.s" | "fdiv.d" | "div.s" | "div.d" => fff(Instrution::Fdiv)?,
+            "beq" => Instrution::Beq(
+                self.reg(args[0])?,
+                self.reg(args[1])?,
+                self.target(args[2])?,
+            ),
+            "bne" => Instrution::Bne(
+                self.reg(args[0])?,
+                self.reg(args[1])?,
+                self.target(args[2])?,
+            ),
+            "blt" => Instrution::Blt(
+                self.reg(args[0])?,
+                self.reg(args[1])?,
+                self.target(args[2])?,
+            ),
+            "bge" => Instrution::Bge(
+                self.reg(args[0])?,
+                self.reg(args[1])?,
+                self.target(args[2])?,
+            ),
+            // `jal target` links to ra
+            "jal" if args.len() == 1 => {
+                Instrution::Jal(self.in_range(op, 1)?, self.target(args[0])?)
+            }
+            "jal" => Instrution::Jal(self.reg(args[0])?, self.target(args[1])?),
+            // `jalr rd rs1` has an offset of zero
+            "jalr" if args.len() == 2 => {
+                Instrution::Jalr(self.reg(args[0])?, 0, self.reg(args[1])?)
+            }
+            _ => Instrution::Jalr(self.reg(args[0])?, self.imm(args[1])?, self.reg(args[2])?),
+        })
+    }
+}
+
+impl Instrution {
+    /// Parse a single instruction. Branch targets must be instruction
+    /// indices, use [`assemble`] for programs with labels.
+    pub fn parse(line: &str) -> Result<Self, Diagnostic> {
+        let labels = HashMap::new();
+        let parser = Parser {
+            line: 1,
+            labels: &labels,
+            regs: MAX_REGS,
+        };
+        let tokens = tokenize(strip_comment(line));
+        if tokens.is_empty() {
+            return Err(parser.error(1, "expected an instruction".to_owned()));
+        }
+        parser.instruction(&tokens)
+    }
+}
+
+impl std::str::FromStr for Instrution {
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Assemble a program for a machine with `regs` registers. Lines may carry
+/// `label:` definitions and `#`, `;` or `//` comments, and branch and jump
+/// targets may name a label instead of an instruction index. Every error in
+/// the program is reported, not only the first.
+pub fn assemble(src: &str, regs: usize) -> Result<Vec<Instrution>, Diagnostics> {
+    let mut errors = Vec::new();
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    src.lines().enumerate().for_each(|(i, v)| {
+        let mut tokens = tokenize(strip_comment(v));
+        while let Some(label) = tokens.first().and_then(|v| v.text.strip_suffix(':')) {
+            if !is_label(label) {
+                errors.push(Diagnostic {
+                    line: i + 1,
+                    column: tokens[0].column,
+                    message: format!("`{}` is not a valid label", label),
+                });
+            } else if labels.insert(label, lines.len() as u32).is_some() {
+                errors.push(Diagnostic {
+                    line: i + 1,
+                    column: tokens[0].column,
+                    message: format!("label `{}` is defined twice", label),
+                });
+            }
+            tokens.remove(0);
+        }
+        if !tokens.is_empty() {
+            lines.push((i + 1, tokens));
+        }
+    });
+    let instrs = lines
+        .iter()
+        .filter_map(|(line, tokens)| {
+            let parser = Parser {
+                line: *line,
+                labels: &labels,
+                regs: regs.min(MAX_REGS),
+            };
+            parser.instruction(tokens).map_err(|e| errors.push(e)).ok()
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(instrs)
+    } else {
+        errors.sort_by_key(|v| (v.line, v.column));
+        Err(Diagnostics(errors))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assemble, Diagnostic};
+    use crate::comp::pc::Instrution;
+    use crate::comp::reg::RegIndex;
+
+    #[test]
+    fn syntax() {
+        let instrs = assemble(
+            "start:\tlw a0, -20(s0)   # load\n  sw t1,(sp)\n\nloop: add x1,x1,  x2 ; again\nbne x1 x3 loop\n// done\njal end\nend: jalr zero, ra",
+            32,
+        )
+        .unwrap();
+        let str = serde_json::to_string(&instrs).unwrap();
+        assert_eq!(
+            str,
+            r#"[{"Lw":[10,-20,8]},{"Sw":[6,0,2]},{"Add":[1,1,2]},{"Bne":[1,3,2]},{"Jal":[1,5]},{"Jalr":[0,0,1]}]"#
+        );
+        let instrs = assemble("lw x1 200 x2\nadd x200 x255 x0", 256).unwrap();
+        assert_eq!(
+            instrs,
+            vec![
+                Instrution::Lw(RegIndex(1), 200, RegIndex(2)),
+                Instrution::Add(RegIndex(200), RegIndex(255), RegIndex(0))
+            ]
+        );
+        assert_eq!(assemble("add x1 x2 x20", 16).unwrap_err().0[0].column, 11);
+        let instrs = assemble("L.D F6,34(R2)\nMUL.D F0,F2,F4\nfsw f0 8(x1)", 32).unwrap();
+        assert_eq!(
+            instrs,
+            vec![
+                Instrution::Flw(RegIndex(6), 34, RegIndex(2)),
+                Instrution::Fmul(RegIndex(0), RegIndex(2), RegIndex(4)),
+                Instrution::Fsw(RegIndex(0), 8, RegIndex(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn all_errors() {
+        let err = assemble(
+            "add x1 x2\nfoo x1 x2 x3\nlw x1 3000 x2\nbeq x1 x2 nowhere\nadd x1 x40 x2\nslli x1 x1 32",
+            32,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.0.iter().map(|v| (v.line, v.column)).collect::<Vec<_>>(),
+            vec![(1, 1), (2, 1), (3, 7), (4, 11), (5, 8), (6, 12)]
+        );
+        assert_eq!(
+            "fadd.s f1 f2 x3".parse::<Instrution>().unwrap_err().column,
+            14
+        );
+        assert_eq!(
+            "add x1 x2 q1".parse::<Instrution>(),
+            Err(Diagnostic {
+                line: 1,
+                column: 11,
+                message: "expected a register, found `q1`".to_owned()
+            })
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use super::rob::{Rob, RobTag};
+use super::rs::RsType;
+
+/// A slot that finished executing and asks for a bus to broadcast its result.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Request {
+    pub class: RsType,
+    /// Index of the slot within its class.
+    pub slot: usize,
+    pub tag: RobTag,
+}
+
+/// Policy deciding which requests get a bus when there are more requests
+/// than buses. Losers keep their result and ask again next cycle.
+pub trait Arbiter: Debug {
+    fn name(&self) -> &'static str;
+    /// Pick at most `buses` of `requests`, returning their indices.
+    fn grant(&mut self, requests: &[Request], rob: &Rob, buses: usize) -> Vec<usize>;
+    fn box_clone(&self) -> Box<dyn Arbiter>;
+}
+
+impl Clone for Box<dyn Arbiter> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// The instruction that is earliest in program order wins.
+#[derive(Default, Clone, Debug)]
+pub struct OldestFirst;
+
+impl Arbiter for OldestFirst {
+    fn name(&self) -> &'static str {
+        "oldest first"
+    }
+    fn grant(&mut self, requests: &[Request], rob: &Rob, buses: usize) -> Vec<usize> {
+        let mut order = (0..requests.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| rob.age(requests[i].tag));
+        order.truncate(buses);
+        order
+    }
+    fn box_clone(&self) -> Box<dyn Arbiter> {
+        Box::new(self.clone())
+    }
+}
+
+/// Classes win in a fixed order, lowest slot first within a class.
+#[derive(Clone, Debug)]
+pub struct ClassPriority {
+    pub order: Vec<RsType>,
+}
+
+impl Default for ClassPriority {
+    fn default() -> Self {
+        Self {
+            order: RsType::ALL.to_vec(),
+        }
+    }
+}
+
+impl Arbiter for ClassPriority {
+    fn name(&self) -> &'static str {
+        "class priority"
+    }
+    fn grant(&mut self, requests: &[Request], _rob: &Rob, buses: usize) -> Vec<usize> {
+        let rank = |v: &Request| {
+            let class = self.order.iter().position(|c| *c == v.class);
+            (class.unwrap_or(self.order.len()), v.slot)
+        };
+        let mut order = (0..requests.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| rank(&requests[i]));
+        order.truncate(buses);
+        order
+    }
+    fn box_clone(&self) -> Box<dyn Arbiter> {
+        Box::new(self.clone())
+    }
+}
+
+/// Classes take turns: the class after the last one granted goes first.
+#[derive(Default, Clone, Debug)]
+pub struct RoundRobin {
+    pub next: usize,
+}
+
+impl Arbiter for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round robin"
+    }
+    fn grant(&mut self, requests: &[Request], _rob: &Rob, buses: usize) -> Vec<usize> {
+        let classes = RsType::ALL.len();
+        let turn = |v: &Request| {
+            let class = RsType::ALL.iter().position(|c| *c == v.class).unwrap();
+            ((class + classes - self.next) % classes, v.slot)
+        };
+        let mut order = (0..requests.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| turn(&requests[i]));
+        order.truncate(buses);
+        if let Some(&last) = order.last() {
+            let class = RsType::ALL
+                .iter()
+                .position(|c| *c == requests[last].class)
+                .unwrap();
+            self.next = (class + 1) % classes;
+        }
+        order
+    }
+    fn box_clone(&self) -> Box<dyn Arbiter> {
+        Box::new(self.clone())
+    }
+}
+
+/// Arbitration policy selectable from the machine configuration.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Arbitration {
+    #[default]
+    ClassPriority,
+    OldestFirst,
+    RoundRobin,
+}
+
+impl Arbitration {
+    pub fn build(&self) -> Box<dyn Arbiter> {
+        match self {
+            Arbitration::ClassPriority => Box::<ClassPriority>::default(),
+            Arbitration::OldestFirst => Box::new(OldestFirst),
+            Arbitration::RoundRobin => Box::<RoundRobin>::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Default, Clone, Copy, PartialEq, Debug)]
+pub struct CdbStats {
+    /// Results broadcast.
+    pub broadcasts: u64,
+    /// Cycles with at least one bus in use.
+    pub busy_cycles: u64,
+    /// Requests that lost arbitration, counted once per cycle of waiting.
+    pub conflicts: u64,
+    /// Cycles in which at least one request lost.
+    pub conflict_cycles: u64,
+}
+
+/// The common data buses. Every cycle the finished slots request a bus and
+/// the arbiter picks at most `buses` of them.
+#[derive(Clone, Debug)]
+pub struct Cdb {
+    pub buses: usize,
+    pub arbiter: Box<dyn Arbiter>,
+    pub stats: CdbStats,
+}
+
+impl Default for Cdb {
+    fn default() -> Self {
+        Self::new(1, Arbitration::default())
+    }
+}
+
+impl Cdb {
+    pub fn new(buses: usize, policy: Arbitration) -> Self {
+        Self {
+            buses,
+            arbiter: policy.build(),
+            stats: CdbStats::default(),
+        }
+    }
+
+    /// Indices of the requests granted a bus this cycle.
+    pub fn arbitrate(&mut self, requests: &[Request], rob: &Rob) -> Vec<usize> {
+        let granted = self.arbiter.grant(requests, rob, self.buses);
+        let lost = (requests.len() - granted.len()) as u64;
+        self.stats.broadcasts += granted.len() as u64;
+        self.stats.busy_cycles += !granted.is_empty() as u64;
+        self.stats.conflicts += lost;
+        self.stats.conflict_cycles += (lost > 0) as u64;
+        granted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Arbitration, Cdb, Request};
+    use crate::comp::pc::Instrution;
+    use crate::comp::reg::RegIndex;
+    use crate::comp::rob::Rob;
+    use crate::comp::rs::RsType;
+
+    #[test]
+    fn policies() {
+        let mut rob = Rob::new(4);
+        for i in 0..3 {
+            rob.push(
+                Instrution::Add(RegIndex(1), RegIndex(2), RegIndex(3)),
+                Some(RegIndex(1)),
+                i,
+                i + 1,
+            )
+            .unwrap();
+        }
+        // the load is the oldest, the mul the youngest
+        let requests = [
+            Request {
+                class: RsType::Add,
+                slot: 1,
+                tag: 1,
+            },
+            Request {
+                class: RsType::Mul,
+                slot: 0,
+                tag: 2,
+            },
+            Request {
+                class: RsType::Load,
+                slot: 0,
+                tag: 0,
+            },
+        ];
+        let mut cdb = Cdb::new(1, Arbitration::ClassPriority);
+        assert_eq!(cdb.arbitrate(&requests, &rob), vec![0]);
+        let mut cdb = Cdb::new(1, Arbitration::OldestFirst);
+        assert_eq!(cdb.arbitrate(&requests, &rob), vec![2]);
+        let mut cdb = Cdb::new(1, Arbitration::RoundRobin);
+        assert_eq!(cdb.arbitrate(&requests, &rob), vec![0]);
+        assert_eq!(cdb.arbitrate(&requests, &rob), vec![1]);
+        assert_eq!(cdb.arbitrate(&requests, &rob), vec![2]);
+        assert_eq!(cdb.arbitrate(&requests, &rob), vec![0]);
+        assert_eq!(cdb.stats.broadcasts, 4);
+        assert_eq!(cdb.stats.conflicts, 8);
+        let mut cdb = Cdb::new(2, Arbitration::OldestFirst);
+        assert_eq!(cdb.arbitrate(&requests, &rob), vec![2, 0]);
+        assert_eq!(cdb.stats.conflict_cycles, 1);
+    }
+}
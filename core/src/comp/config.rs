@@ -0,0 +1,465 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::Path;
+
+use super::cdb::Arbitration;
+use super::fu::Dispatch;
+use super::lsq::{Disambiguation, DEFAULT_LSQ_SIZE};
+use super::mem::DEFAULT_MEM_SIZE;
+use super::pc::Instrution;
+use super::predictor::{Prediction, DEFAULT_PREDICTOR_ENTRIES};
+use super::rename::Renaming;
+use super::rs::RsType;
+
+/// A value for each reservation station class, a number unless stated
+/// otherwise.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct ClassCount<T = usize> {
+    pub load: T,
+    pub store: T,
+    pub add: T,
+    pub mul: T,
+}
+
+impl<T> ClassCount<T> {
+    pub fn get(&self, class: RsType) -> &T {
+        match class {
+            RsType::Load => &self.load,
+            RsType::Store => &self.store,
+            RsType::Add => &self.add,
+            RsType::Mul => &self.mul,
+        }
+    }
+
+    pub fn get_mut(&mut self, class: RsType) -> &mut T {
+        match class {
+            RsType::Load => &mut self.load,
+            RsType::Store => &mut self.store,
+            RsType::Add => &mut self.add,
+            RsType::Mul => &mut self.mul,
+        }
+    }
+}
+
+/// Read a count per class, where the classes missing from a partial table
+/// keep their count in `default` rather than zero.
+fn partial_counts<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    default: ClassCount,
+) -> Result<ClassCount, D::Error> {
+    let counts = ClassCount::<Option<usize>>::deserialize(deserializer)?;
+    Ok(ClassCount {
+        load: counts.load.unwrap_or(default.load),
+        store: counts.store.unwrap_or(default.store),
+        add: counts.add.unwrap_or(default.add),
+        mul: counts.mul.unwrap_or(default.mul),
+    })
+}
+
+fn stations<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ClassCount, D::Error> {
+    partial_counts(deserializer, MachineConfig::default().stations)
+}
+
+fn units<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ClassCount, D::Error> {
+    partial_counts(deserializer, MachineConfig::default().units)
+}
+
+/// Execution time of each opcode, in the cycles a slot counts down. Fields
+/// are named after the mnemonics, with `fadd` for fadd.s and so on.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct Latency {
+    pub lw: u8,
+    pub sw: u8,
+    pub add: u8,
+    pub sub: u8,
+    pub sll: u8,
+    pub slt: u8,
+    pub sltu: u8,
+    pub xor: u8,
+    pub srl: u8,
+    pub sra: u8,
+    pub or: u8,
+    pub and: u8,
+    pub mul: u8,
+    pub div: u8,
+    pub rem: u8,
+    pub addi: u8,
+    pub slti: u8,
+    pub sltiu: u8,
+    pub xori: u8,
+    pub ori: u8,
+    pub andi: u8,
+    pub slli: u8,
+    pub srli: u8,
+    pub srai: u8,
+    pub lui: u8,
+    pub auipc: u8,
+    pub beq: u8,
+    pub bne: u8,
+    pub blt: u8,
+    pub bge: u8,
+    pub jal: u8,
+    pub jalr: u8,
+    pub flw: u8,
+    pub fsw: u8,
+    pub fadd: u8,
+    pub fsub: u8,
+    pub fmul: u8,
+    pub fdiv: u8,
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self {
+            lw: 2,
+            sw: 2,
+            add: 2,
+            sub: 2,
+            sll: 2,
+            slt: 2,
+            sltu: 2,
+            xor: 2,
+            srl: 2,
+            sra: 2,
+            or: 2,
+            and: 2,
+            mul: 10,
+            div: 40,
+            rem: 40,
+            addi: 2,
+            slti: 2,
+            sltiu: 2,
+            xori: 2,
+            ori: 2,
+            andi: 2,
+            slli: 2,
+            srli: 2,
+            srai: 2,
+            lui: 2,
+            auipc: 2,
+            beq: 1,
+            bne: 1,
+            blt: 1,
+            bge: 1,
+            jal: 1,
+            jalr: 1,
+            flw: 2,
+            fsw: 2,
+            fadd: 2,
+            fsub: 2,
+            fmul: 10,
+            fdiv: 40,
+        }
+    }
+}
+
+impl Latency {
+    pub fn get(&self, instr: Instrution) -> u8 {
+        match instr {
+            Instrution::Lw(..) => self.lw,
+            Instrution::Sw(..) => self.sw,
+            Instrution::Add(..) => self.add,
+            Instrution::Sub(..) => self.sub,
+            Instrution::Sll(..) => self.sll,
+            Instrution::Slt(..) => self.slt,
+            Instrution::Sltu(..) => self.sltu,
+            Instrution::Xor(..) => self.xor,
+            Instrution::Srl(..) => self.srl,
+            Instrution::Sra(..) => self.sra,
+            Instrution::Or(..) => self.or,
+            Instrution::And(..) => self.and,
+            Instrution::Mul(..) => self.mul,
+            Instrution::Div(..) => self.div,
+            Instrution::Rem(..) => self.rem,
+            Instrution::Addi(..) => self.addi,
+            Instrution::Slti(..) => self.slti,
+            Instrution::Sltiu(..) => self.sltiu,
+            Instrution::Xori(..) => self.xori,
+            Instrution::Ori(..) => self.ori,
+            Instrution::Andi(..) => self.andi,
+            Instrution::Slli(..) => self.slli,
+            Instrution::Srli(..) => self.srli,
+            Instrution::Srai(..) => self.srai,
+            Instrution::Lui(..) => self.lui,
+            Instrution::Auipc(..) => self.auipc,
+            Instrution::Beq(..) => self.beq,
+            Instrution::Bne(..) => self.bne,
+            Instrution::Blt(..) => self.blt,
+            Instrution::Bge(..) => self.bge,
+            Instrution::Jal(..) => self.jal,
+            Instrution::Jalr(..) => self.jalr,
+            Instrution::Flw(..) => self.flw,
+            Instrution::Fsw(..) => self.fsw,
+            Instrution::Fadd(..) => self.fadd,
+            Instrution::Fsub(..) => self.fsub,
+            Instrution::Fmul(..) => self.fmul,
+            Instrution::Fdiv(..) => self.fdiv,
+        }
+    }
+
+    /// Every latency with the name of its field.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u8)> {
+        [
+            ("lw", self.lw),
+            ("sw", self.sw),
+            ("add", self.add),
+            ("sub", self.sub),
+            ("sll", self.sll),
+            ("slt", self.slt),
+            ("sltu", self.sltu),
+            ("xor", self.xor),
+            ("srl", self.srl),
+            ("sra", self.sra),
+            ("or", self.or),
+            ("and", self.and),
+            ("mul", self.mul),
+            ("div", self.div),
+            ("rem", self.rem),
+            ("addi", self.addi),
+            ("slti", self.slti),
+            ("sltiu", self.sltiu),
+            ("xori", self.xori),
+            ("ori", self.ori),
+            ("andi", self.andi),
+            ("slli", self.slli),
+            ("srli", self.srli),
+            ("srai", self.srai),
+            ("lui", self.lui),
+            ("auipc", self.auipc),
+            ("beq", self.beq),
+            ("bne", self.bne),
+            ("blt", self.blt),
+            ("bge", self.bge),
+            ("jal", self.jal),
+            ("jalr", self.jalr),
+            ("flw", self.flw),
+            ("fsw", self.fsw),
+            ("fadd", self.fadd),
+            ("fsub", self.fsub),
+            ("fmul", self.fmul),
+            ("fdiv", self.fdiv),
+        ]
+        .into_iter()
+    }
+}
+
+/// Description of the simulated machine. Every structure is sized from it
+/// when a program is loaded.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct MachineConfig {
+    /// Reservation stations per class.
+    #[serde(deserialize_with = "stations")]
+    pub stations: ClassCount,
+    /// Functional units per class.
+    #[serde(deserialize_with = "units")]
+    pub units: ClassCount,
+    /// Whether the units of a class accept a new operation every cycle
+    /// instead of only once the previous one finished.
+    pub pipelined: ClassCount<bool>,
+    /// Which ready stations get a free unit first.
+    pub dispatch: Dispatch,
+    pub latency: Latency,
+    /// Number of common data buses, i.e. results broadcast per cycle.
+    pub cdb: usize,
+    /// Which finished result gets a CDB when there are too many.
+    pub arbitration: Arbitration,
+    /// Number of architectural registers.
+    pub regs: usize,
+    /// Number of reorder buffer entries.
+    pub rob: usize,
+    /// Number of load/store queue entries.
+    pub lsq: usize,
+    pub disambiguation: Disambiguation,
+    /// Whether to keep issuing past unresolved branches. Without it issue
+    /// stalls until every branch in flight has resolved.
+    pub speculation: bool,
+    pub predictor: Prediction,
+    /// Counters in the table of the two-bit predictor.
+    pub predictor_entries: usize,
+    pub renaming: Renaming,
+    /// Size of the unified physical register file, only used with
+    /// [`Renaming::PhysicalRegisters`]. It needs more registers than both
+    /// architectural files together.
+    pub physical_regs: usize,
+    /// Bytes of data memory.
+    pub mem_size: usize,
+    /// Initial memory contents, consecutive words from address 0.
+    pub mem_words: Vec<i32>,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            stations: ClassCount {
+                load: 3,
+                store: 3,
+                add: 3,
+                mul: 2,
+            },
+            units: ClassCount {
+                load: 1,
+                store: 1,
+                add: 1,
+                mul: 1,
+            },
+            pipelined: ClassCount::default(),
+            dispatch: Dispatch::default(),
+            latency: Latency::default(),
+            cdb: 1,
+            arbitration: Arbitration::default(),
+            regs: 32,
+            rob: 8,
+            lsq: DEFAULT_LSQ_SIZE,
+            disambiguation: Disambiguation::default(),
+            speculation: true,
+            predictor: Prediction::default(),
+            predictor_entries: DEFAULT_PREDICTOR_ENTRIES,
+            renaming: Renaming::default(),
+            physical_regs: 96,
+            mem_size: DEFAULT_MEM_SIZE,
+            mem_words: Vec::new(),
+        }
+    }
+}
+
+impl MachineConfig {
+    pub fn from_toml(src: &str) -> Result<Self> {
+        let config = toml::from_str::<Self>(src)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_json(src: &str) -> Result<Self> {
+        let config = serde_json::from_str::<Self>(src)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Load a `.toml` or `.json` file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|v| v.to_str()) {
+            Some("json") => Self::from_json(&src),
+            _ => Self::from_toml(&src),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap()
+    }
+
+    /// Check that a machine can be built from the configuration. Parsing
+    /// does it already, configurations built in code are checked on load.
+    pub fn validate(&self) -> Result<()> {
+        let ClassCount {
+            load,
+            store,
+            add,
+            mul,
+        } = self.stations;
+        if [load, store, add, mul].contains(&0) {
+            return Err(anyhow!("Every class needs at least one station"));
+        }
+        let ClassCount {
+            load,
+            store,
+            add,
+            mul,
+        } = self.units;
+        if [load, store, add, mul].contains(&0) {
+            return Err(anyhow!("Every class needs at least one functional unit"));
+        }
+        if let Some((name, _)) = self.latency.iter().find(|(_, v)| *v == 0) {
+            return Err(anyhow!(
+                "The latency of {} must be at least one cycle",
+                name
+            ));
+        }
+        if self.cdb == 0 {
+            return Err(anyhow!("At least one CDB is needed"));
+        }
+        if self.regs == 0 || self.regs > 256 {
+            return Err(anyhow!("Register count must be between 1 and 256"));
+        }
+        if self.rob == 0 {
+            return Err(anyhow!("The ROB needs at least one entry"));
+        }
+        if self.lsq == 0 {
+            return Err(anyhow!("The load/store queue needs at least one entry"));
+        }
+        if self.predictor_entries == 0 {
+            return Err(anyhow!("The branch predictor needs at least one entry"));
+        }
+        if self.mem_words.len() * 4 > self.mem_size {
+            return Err(anyhow!(
+                "{} initial words do not fit in {} bytes of memory",
+                self.mem_words.len(),
+                self.mem_size
+            ));
+        }
+        if self.renaming == Renaming::PhysicalRegisters && self.physical_regs <= self.regs * 2 {
+            return Err(anyhow!(
+                "{} physical registers cannot rename {} integer and {} FP registers",
+                self.physical_regs,
+                self.regs,
+                self.regs
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MachineConfig;
+    use crate::comp::cdb::Arbitration;
+
+    #[test]
+    fn parse_partial() {
+        let config = MachineConfig::from_toml(
+            r#"
+cdb = 2
+arbitration = "round_robin"
+[stations]
+load = 1
+store = 1
+add = 4
+mul = 1
+[latency]
+mul = 4
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.cdb, 2);
+        assert_eq!(config.arbitration, Arbitration::RoundRobin);
+        assert_eq!(config.stations.add, 4);
+        assert_eq!(config.latency.mul, 4);
+        assert_eq!(config.latency.div, 40);
+        assert_eq!(config.regs, 32);
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(MachineConfig::from_json(&json).unwrap(), config);
+        assert_eq!(MachineConfig::from_toml(&config.to_toml()).unwrap(), config);
+        assert!(MachineConfig::from_toml("cdb = 0").is_err());
+        assert!(MachineConfig::from_toml("predictor_entries = 0").is_err());
+        assert!(MachineConfig::from_toml("mem_size = 8\nmem_words = [1, 2, 3]").is_err());
+        let config = MachineConfig::from_toml("mem_size = 16\nmem_words = [1, 2]").unwrap();
+        assert_eq!((config.mem_size, config.mem_words), (16, vec![1, 2]));
+        assert!(MachineConfig::from_toml("[latency]\nmul = 0").is_err());
+        assert!(MachineConfig::from_toml("[latency]\nfdiv = -128").is_err());
+        // missing classes and opcodes keep the defaults of the machine
+        let config = MachineConfig::from_toml("[stations]\nadd = 4\n[latency]\naddi = 1").unwrap();
+        assert_eq!(config.stations.add, 4);
+        assert_eq!(config.stations.mul, 2);
+        assert_eq!(config.latency.addi, 1);
+        assert_eq!(config.latency.add, 2);
+        let config = MachineConfig::from_toml("[pipelined]\nmul = true").unwrap();
+        assert!(config.pipelined.mul && !config.pipelined.add);
+        let renaming = "renaming = \"physical_registers\"\nphysical_regs = 64";
+        assert!(MachineConfig::from_toml(renaming).is_err());
+        assert!(MachineConfig::from_toml(&format!("{}\nregs = 16", renaming)).is_ok());
+    }
+}
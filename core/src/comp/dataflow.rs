@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use super::pc::Instrution;
+use super::reg::RegIndex;
+use super::rob::RobTag;
+use super::rs::RsType;
+use super::Tomasulo;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Node {
+    /// A busy reservation station entry.
+    Station {
+        class: RsType,
+        index: usize,
+        tag: RobTag,
+        instr: Instrution,
+    },
+    /// A result on the CDB this cycle. Its station has already been freed.
+    Broadcast { tag: RobTag, instr: Instrution },
+    /// An architectural register waiting for a result.
+    Reg { reg: RegIndex, fp: bool },
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Station {
+                class,
+                index,
+                tag,
+                instr,
+            } => write!(f, "{}{} rob{}\n{}", class, index, tag, instr),
+            Node::Broadcast { tag, instr } => write!(f, "CDB rob{}\n{}", tag, instr),
+            Node::Reg { reg, fp: false } => write!(f, "x{}", reg),
+            Node::Reg { reg, fp: true } => write!(f, "f{}", reg),
+        }
+    }
+}
+
+/// `to` waits on the result of `from`, indices into [`Dataflow::nodes`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub tag: RobTag,
+    /// The result is on the CDB this cycle and has just been received.
+    pub delivered: bool,
+}
+
+/// Producer/consumer graph of the instructions in flight: the Q tags of the
+/// stations and the status of the registers.
+#[derive(Default, Clone, Debug)]
+pub struct Dataflow {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Dataflow {
+    /// The graph at the end of the current cycle of `t`.
+    pub fn new(t: &Tomasulo) -> Self {
+        let mut graph = Self::default();
+        let mut producers = HashMap::new();
+        for class in RsType::ALL {
+            for (index, slot) in t.rs.slots(class).iter().enumerate() {
+                if let (true, Some(tag), Some(instr)) = (slot.busy, slot.dest, slot.op) {
+                    producers.insert(tag, graph.nodes.len());
+                    graph.nodes.push(Node::Station {
+                        class,
+                        index,
+                        tag,
+                        instr,
+                    });
+                }
+            }
+        }
+        let stations = graph.nodes.len();
+        for (tag, entry) in t.rob.iter() {
+            let instr = entry.instr.unwrap();
+            if entry.timing.write_result == Some(t.cycle()) && instr.writes_back() {
+                producers.insert(tag, graph.nodes.len());
+                graph.nodes.push(Node::Broadcast { tag, instr });
+            }
+        }
+
+        for to in 0..stations {
+            let Node::Station {
+                class, index, tag, ..
+            } = graph.nodes[to]
+            else {
+                continue;
+            };
+            let slot = &t.rs.slots(class)[index];
+            for from in [slot.qj, slot.qk].into_iter().flatten() {
+                if let Some(&producer) = producers.get(&from) {
+                    graph.edge(producer, to, from, false);
+                }
+            }
+            // operands that arrived on the CDB this cycle
+            for from in t.rob.get(tag).sources.into_iter().flatten() {
+                let Some(&producer) = producers.get(&from) else {
+                    continue;
+                };
+                let older = t.rob.age(from) < t.rob.age(tag);
+                if older && matches!(graph.nodes[producer], Node::Broadcast { .. }) {
+                    graph.edge(producer, to, from, true);
+                }
+            }
+        }
+
+        let count = t.rg.regs.len();
+        let regs = (0..count).map(|v| (v, false));
+        for (i, fp) in regs.chain((0..count).map(|v| (v, true))) {
+            let reg = RegIndex(i as u8);
+            let Some(tag) = t.renamer().producer(reg, fp) else {
+                continue;
+            };
+            let Some(&producer) = producers.get(&tag) else {
+                continue;
+            };
+            if i == 0 && !fp {
+                continue;
+            }
+            graph.nodes.push(Node::Reg { reg, fp });
+            graph.edge(producer, graph.nodes.len() - 1, tag, false);
+        }
+        graph
+    }
+
+    fn edge(&mut self, from: usize, to: usize, tag: RobTag, delivered: bool) {
+        if !self.edges.iter().any(|v| v.from == from && v.to == to) {
+            self.edges.push(Edge {
+                from,
+                to,
+                tag,
+                delivered,
+            });
+        }
+    }
+
+    /// Column of each node for drawing from left to right: producers with
+    /// nothing to wait on first, every consumer right of its producers, and
+    /// registers last.
+    pub fn layers(&self) -> Vec<usize> {
+        let mut layers = vec![0; self.nodes.len()];
+        // producers are older than their consumers, so the graph has no
+        // cycles and settles within one pass per node
+        for _ in 0..self.nodes.len() {
+            let mut changed = false;
+            for edge in self.edges.iter() {
+                if layers[edge.to] <= layers[edge.from] {
+                    layers[edge.to] = layers[edge.from] + 1;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        let last = self
+            .nodes
+            .iter()
+            .zip(layers.iter())
+            .filter(|(v, _)| !matches!(v, Node::Reg { .. }))
+            .map(|(_, v)| v + 1)
+            .max()
+            .unwrap_or(0);
+        self.nodes
+            .iter()
+            .zip(layers)
+            .map(|(v, layer)| match v {
+                Node::Reg { .. } => last,
+                _ => layer,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Dataflow, Node};
+    use crate::comp::reg::RegIndex;
+    use crate::comp::rs::RsType;
+    use crate::comp::Tomasulo;
+
+    #[test]
+    fn chain() {
+        let mut t = Tomasulo::default();
+        t.init_instruction("mul x1 x2 x3\nadd x4 x1 x2\nadd x5 x4 x1")
+            .unwrap();
+        t.run_to(3).unwrap();
+        let graph = Dataflow::new(&t);
+        let name = |i: usize| match graph.nodes[i] {
+            Node::Station { class, index, .. } => format!("{}{}", class, index),
+            v => v.to_string(),
+        };
+        let mut edges = graph
+            .edges
+            .iter()
+            .map(|v| (name(v.from), name(v.to), v.delivered))
+            .collect::<Vec<_>>();
+        edges.sort();
+        let edge = |a: &str, b: &str| (a.to_owned(), b.to_owned(), false);
+        assert_eq!(
+            edges,
+            vec![
+                edge("add0", "add1"),
+                edge("add0", "x4"),
+                edge("add1", "x5"),
+                edge("mul0", "add0"),
+                edge("mul0", "add1"),
+                edge("mul0", "x1"),
+            ]
+        );
+        let layers = graph.layers();
+        let layer = |name: &str| {
+            layers[(0..graph.nodes.len())
+                .find(|&i| graph.nodes[i].to_string().starts_with(name))
+                .unwrap()]
+        };
+        assert_eq!((layer("mul0"), layer("add0"), layer("add1")), (0, 1, 2));
+        assert_eq!((layer("x1"), layer("x5")), (3, 3));
+
+        // the mul broadcasts in cycle 12 and both adds receive x1
+        t.run_to(9).unwrap();
+        let graph = Dataflow::new(&t);
+        assert!(graph
+            .nodes
+            .iter()
+            .any(|v| matches!(v, Node::Broadcast { tag: 0, .. })));
+        assert_eq!(graph.edges.iter().filter(|v| v.delivered).count(), 2);
+        assert!(graph.nodes.contains(&Node::Reg {
+            reg: RegIndex(4),
+            fp: false
+        }));
+        assert!(!graph.nodes.iter().any(|v| matches!(
+            v,
+            Node::Station {
+                class: RsType::Mul,
+                ..
+            }
+        )));
+    }
+}
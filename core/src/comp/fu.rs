@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+/// Order in which ready stations of a class are handed free units.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Dispatch {
+    #[default]
+    LowestIndex,
+    OldestFirst,
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Unit {
+    /// Station executing on this unit. Only unpipelined units are held for
+    /// the whole execution.
+    pub slot: Option<usize>,
+    /// Last cycle an operation entered the unit.
+    pub accepted: Option<u32>,
+}
+
+/// The functional units of one class. A pipelined unit accepts a new
+/// operation every cycle, an unpipelined one only after the previous
+/// operation finished executing.
+#[derive(Default, Clone, Debug)]
+pub struct FuPool {
+    pub units: Vec<Unit>,
+    pub pipelined: bool,
+    pub dispatch: Dispatch,
+}
+
+impl FuPool {
+    pub fn new(count: usize, pipelined: bool, dispatch: Dispatch) -> Self {
+        Self {
+            units: vec![Unit::default(); count],
+            pipelined,
+            dispatch,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.units.iter_mut().for_each(|v| *v = Unit::default());
+    }
+
+    /// Start the operation of `slot` on a free unit, returning the unit.
+    pub fn dispatch(&mut self, slot: usize, cycle: u32) -> Option<usize> {
+        let pipelined = self.pipelined;
+        let (i, unit) = self.units.iter_mut().enumerate().find(|(_, v)| {
+            if pipelined {
+                v.accepted != Some(cycle)
+            } else {
+                v.slot.is_none()
+            }
+        })?;
+        unit.accepted = Some(cycle);
+        if !pipelined {
+            unit.slot = Some(slot);
+        }
+        Some(i)
+    }
+
+    /// The operation on `unit` finished executing.
+    pub fn release(&mut self, unit: usize) {
+        self.units[unit].slot = None;
+    }
+
+    /// Units that cannot accept an operation in `cycle`.
+    pub fn busy(&self, cycle: u32) -> usize {
+        self.units
+            .iter()
+            .filter(|v| v.slot.is_some() || v.accepted == Some(cycle))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Dispatch, FuPool};
+
+    #[test]
+    fn pipelining() {
+        let mut fu = FuPool::new(1, false, Dispatch::LowestIndex);
+        assert_eq!(fu.dispatch(0, 1), Some(0));
+        assert_eq!(fu.dispatch(1, 2), None);
+        fu.release(0);
+        assert_eq!(fu.dispatch(1, 3), Some(0));
+
+        let mut fu = FuPool::new(1, true, Dispatch::LowestIndex);
+        assert_eq!(fu.dispatch(0, 1), Some(0));
+        assert_eq!(fu.dispatch(1, 1), None);
+        assert_eq!(fu.dispatch(1, 2), Some(0));
+        assert_eq!(fu.busy(2), 1);
+        assert_eq!(fu.busy(3), 0);
+    }
+}
@@ -0,0 +1,189 @@
+//! Differential fuzzing: random straight-line programs run on random
+//! machines must end in the same state as on the golden model. Programs only
+//! use a handful of registers and memory words so that nearly every
+//! instruction has a RAW, WAR or WAW hazard with a neighbour, and a failing
+//! program is shrunk to a minimal one by proptest.
+//!
+//! Loads and stores address memory through x0 or one of two base registers.
+//! Those are only written by masking another register, which keeps every
+//! access in bounds while making addresses depend on earlier results.
+
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use super::cdb::Arbitration;
+use super::config::{ClassCount, MachineConfig};
+use super::fu::Dispatch;
+use super::golden::check;
+use super::lsq::Disambiguation;
+use super::pc::Instrution;
+use super::reg::RegIndex;
+use super::rename::Renaming;
+use super::Tomasulo;
+
+type Rrr = fn(RegIndex, RegIndex, RegIndex) -> Instrution;
+type Rri = fn(RegIndex, RegIndex, i32) -> Instrution;
+
+const RRR: [Rrr; 13] = [
+    Instrution::Add,
+    Instrution::Sub,
+    Instrution::Sll,
+    Instrution::Slt,
+    Instrution::Sltu,
+    Instrution::Xor,
+    Instrution::Srl,
+    Instrution::Sra,
+    Instrution::Or,
+    Instrution::And,
+    Instrution::Mul,
+    Instrution::Div,
+    Instrution::Rem,
+];
+const RRI: [Rri; 6] = [
+    Instrution::Addi,
+    Instrution::Slti,
+    Instrution::Sltiu,
+    Instrution::Xori,
+    Instrution::Ori,
+    Instrution::Andi,
+];
+const SHIFTS: [Rri; 3] = [Instrution::Slli, Instrution::Srli, Instrution::Srai];
+const FFF: [Rrr; 4] = [
+    Instrution::Fadd,
+    Instrution::Fsub,
+    Instrution::Fmul,
+    Instrution::Fdiv,
+];
+
+/// Bytes of memory the programs touch.
+const MEM_SIZE: usize = 64;
+
+fn reg() -> impl Strategy<Value = RegIndex> {
+    (0u8..6).prop_map(RegIndex)
+}
+
+fn freg() -> impl Strategy<Value = RegIndex> {
+    (0u8..4).prop_map(RegIndex)
+}
+
+/// Largest value of a base register, written as `andi base x BASE_MASK`.
+/// Half-word aligned so that accesses partially overlap as well. The base
+/// registers start out holding their index, which is below it.
+const BASE_MASK: i32 = 0x1e;
+
+fn base() -> impl Strategy<Value = RegIndex> {
+    (6u8..8).prop_map(RegIndex)
+}
+
+/// A base register or x0.
+fn any_base() -> impl Strategy<Value = RegIndex> {
+    prop_oneof![Just(RegIndex(0)), base()]
+}
+
+/// Offset from a base register, half-word aligned like [`BASE_MASK`].
+fn offset() -> impl Strategy<Value = i32> {
+    (0..=(MEM_SIZE as i32 - 4 - BASE_MASK) / 2).prop_map(|v| v * 2)
+}
+
+fn instr() -> impl Strategy<Value = Instrution> {
+    prop_oneof![
+        4 => (select(&RRR[..]), reg(), reg(), reg()).prop_map(|(f, a, b, c)| f(a, b, c)),
+        2 => (select(&RRI[..]), reg(), reg(), -2048..2048).prop_map(|(f, a, b, c)| f(a, b, c)),
+        1 => (select(&SHIFTS[..]), reg(), reg(), 0..32).prop_map(|(f, a, b, c)| f(a, b, c)),
+        1 => (reg(), -(1 << 19)..(1 << 20)).prop_map(|(r, imm)| Instrution::Lui(r, imm)),
+        1 => (reg(), -(1 << 19)..(1 << 20)).prop_map(|(r, imm)| Instrution::Auipc(r, imm)),
+        2 => (base(), reg()).prop_map(|(b, r)| Instrution::Andi(b, r, BASE_MASK)),
+        3 => (reg(), offset(), any_base()).prop_map(|(r, a, b)| Instrution::Lw(r, a, b)),
+        3 => (reg(), offset(), any_base()).prop_map(|(r, a, b)| Instrution::Sw(r, a, b)),
+        1 => (freg(), offset(), any_base()).prop_map(|(r, a, b)| Instrution::Flw(r, a, b)),
+        1 => (freg(), offset(), any_base()).prop_map(|(r, a, b)| Instrution::Fsw(r, a, b)),
+        2 => (select(&FFF[..]), freg(), freg(), freg()).prop_map(|(f, a, b, c)| f(a, b, c)),
+    ]
+}
+
+fn config() -> impl Strategy<Value = MachineConfig> {
+    let stations = (1usize..4, 1usize..4, 1usize..4, 1usize..4);
+    let units = (1usize..3, 1usize..3);
+    let sizes = (1usize..9, 1usize..3, 1usize..9);
+    let policies = (
+        any::<bool>(),
+        select(vec![Dispatch::LowestIndex, Dispatch::OldestFirst]),
+        select(vec![
+            Arbitration::ClassPriority,
+            Arbitration::OldestFirst,
+            Arbitration::RoundRobin,
+        ]),
+        select(vec![
+            Disambiguation::Conservative,
+            Disambiguation::Speculative,
+        ]),
+        any::<bool>(),
+    );
+    // a few spare physical registers so that renaming runs out of them
+    let renaming = (
+        select(vec![Renaming::Rob, Renaming::PhysicalRegisters]),
+        65usize..72,
+    );
+    (stations, units, sizes, policies, renaming).prop_map(
+        |(
+            (load, store, add, mul),
+            (adders, multipliers),
+            (rob, cdb, lsq),
+            (pipelined, dispatch, arbitration, disambiguation, speculation),
+            (renaming, physical_regs),
+        )| MachineConfig {
+            stations: ClassCount {
+                load,
+                store,
+                add,
+                mul,
+            },
+            units: ClassCount {
+                load: 1,
+                store: 1,
+                add: adders,
+                mul: multipliers,
+            },
+            pipelined: ClassCount {
+                load: pipelined,
+                store: pipelined,
+                add: pipelined,
+                mul: pipelined,
+            },
+            dispatch,
+            cdb,
+            arbitration,
+            rob,
+            lsq,
+            disambiguation,
+            speculation,
+            renaming,
+            physical_regs,
+            ..Default::default()
+        },
+    )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn matches_golden_model(
+        program in prop::collection::vec(instr(), 1..40),
+        config in config(),
+        words in prop::collection::vec(any::<i32>(), MEM_SIZE / 4),
+    ) {
+        let mut t = Tomasulo {
+            config: MachineConfig {
+                mem_size: MEM_SIZE,
+                mem_words: words,
+                ..config
+            },
+            ..Default::default()
+        };
+        // straight-line, so the golden model runs each instruction once
+        let count = program.len() as u64;
+        t.load(program).unwrap();
+        prop_assert_eq!(check(&mut t, 10_000, count).unwrap(), vec![]);
+    }
+}
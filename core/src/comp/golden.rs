@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt::Display;
+
+use super::mem::Mem;
+use super::pc::Instrution;
+use super::reg::{RegGroup, RegIndex};
+use super::rs::{div, f32, float, rem};
+use super::Tomasulo;
+
+/// Sequential reference model. It executes one instruction at a time in
+/// program order, so its final state is what the out-of-order machine must
+/// end up with.
+#[derive(Clone, Debug)]
+pub struct Golden {
+    pub instrs: Vec<Instrution>,
+    /// Only the values are used, nothing is ever renamed.
+    pub rg: RegGroup,
+    pub mem: Mem,
+    pub pc: u32,
+    /// Instructions executed so far.
+    pub retired: u64,
+}
+
+impl Golden {
+    /// A model starting from the state `t` had right after loading.
+    pub fn new(t: &Tomasulo) -> Self {
+        let mut mem = t.mem.clone();
+        mem.reset();
+        Self {
+            instrs: t.pc.instrutions.clone(),
+            rg: RegGroup::new(t.rg.regs.len()),
+            mem,
+            pc: 0,
+            retired: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pc as usize >= self.instrs.len()
+    }
+
+    fn x(&self, r: RegIndex) -> i32 {
+        self.rg.get_reg(r).value
+    }
+
+    fn f(&self, r: RegIndex) -> i32 {
+        self.rg.get_freg(r).value
+    }
+
+    fn set_x(&mut self, r: RegIndex, value: i32) {
+        if r.0 != 0 {
+            self.rg.regs[r.index()].value = value;
+        }
+    }
+
+    fn set_f(&mut self, r: RegIndex, value: i32) {
+        self.rg.fregs[r.index()].value = value;
+    }
+
+    /// Execute the next instruction.
+    pub fn step(&mut self) -> Result<()> {
+        let instr = *self
+            .instrs
+            .get(self.pc as usize)
+            .ok_or(anyhow!("No rest instruction"))?;
+        let pc = self.pc;
+        let mut next = pc + 1;
+        let branch = |taken: bool, target: u32| if taken { target } else { pc + 1 };
+        match instr {
+            Instrution::Lw(rd, imm, rs) => {
+                let value = self.mem.load_word(self.x(rs).wrapping_add(imm))?;
+                self.set_x(rd, value);
+            }
+            Instrution::Flw(rd, imm, rs) => {
+                let value = self.mem.load_word(self.x(rs).wrapping_add(imm))?;
+                self.set_f(rd, value);
+            }
+            Instrution::Sw(rs2, imm, rs1) => {
+                self.mem
+                    .store_word(self.x(rs1).wrapping_add(imm), self.x(rs2))?;
+            }
+            Instrution::Fsw(rs2, imm, rs1) => {
+                self.mem
+                    .store_word(self.x(rs1).wrapping_add(imm), self.f(rs2))?;
+            }
+            Instrution::Add(rd, a, b) => self.set_x(rd, self.x(a).wrapping_add(self.x(b))),
+            Instrution::Sub(rd, a, b) => self.set_x(rd, self.x(a).wrapping_sub(self.x(b))),
+            Instrution::Sll(rd, a, b) => self.set_x(rd, self.x(a).wrapping_shl(self.x(b) as u32)),
+            Instrution::Srl(rd, a, b) => {
+                self.set_x(rd, (self.x(a) as u32).wrapping_shr(self.x(b) as u32) as i32)
+            }
+            Instrution::Sra(rd, a, b) => self.set_x(rd, self.x(a).wrapping_shr(self.x(b) as u32)),
+            Instrution::Slt(rd, a, b) => self.set_x(rd, (self.x(a) < self.x(b)) as i32),
+            Instrution::Sltu(rd, a, b) => {
+                self.set_x(rd, ((self.x(a) as u32) < (self.x(b) as u32)) as i32)
+            }
+            Instrution::Xor(rd, a, b) => self.set_x(rd, self.x(a) ^ self.x(b)),
+            Instrution::Or(rd, a, b) => self.set_x(rd, self.x(a) | self.x(b)),
+            Instrution::And(rd, a, b) => self.set_x(rd, self.x(a) & self.x(b)),
+            Instrution::Mul(rd, a, b) => self.set_x(rd, self.x(a).wrapping_mul(self.x(b))),
+            Instrution::Div(rd, a, b) => self.set_x(rd, div(self.x(a), self.x(b))),
+            Instrution::Rem(rd, a, b) => self.set_x(rd, rem(self.x(a), self.x(b))),
+            Instrution::Addi(rd, a, imm) => self.set_x(rd, self.x(a).wrapping_add(imm)),
+            Instrution::Slti(rd, a, imm) => self.set_x(rd, (self.x(a) < imm) as i32),
+            Instrution::Sltiu(rd, a, imm) => {
+                self.set_x(rd, ((self.x(a) as u32) < (imm as u32)) as i32)
+            }
+            Instrution::Xori(rd, a, imm) => self.set_x(rd, self.x(a) ^ imm),
+            Instrution::Ori(rd, a, imm) => self.set_x(rd, self.x(a) | imm),
+            Instrution::Andi(rd, a, imm) => self.set_x(rd, self.x(a) & imm),
+            Instrution::Slli(rd, a, imm) => self.set_x(rd, self.x(a).wrapping_shl(imm as u32)),
+            Instrution::Srli(rd, a, imm) => {
+                self.set_x(rd, (self.x(a) as u32).wrapping_shr(imm as u32) as i32)
+            }
+            Instrution::Srai(rd, a, imm) => self.set_x(rd, self.x(a).wrapping_shr(imm as u32)),
+            Instrution::Lui(rd, imm) => self.set_x(rd, imm.wrapping_shl(12)),
+            // addresses are instruction indices
+            Instrution::Auipc(rd, imm) => self.set_x(rd, (pc as i32).wrapping_add(imm << 12)),
+            Instrution::Fadd(rd, a, b) => self.set_f(rd, float(f32(self.f(a)) + f32(self.f(b)))),
+            Instrution::Fsub(rd, a, b) => self.set_f(rd, float(f32(self.f(a)) - f32(self.f(b)))),
+            Instrution::Fmul(rd, a, b) => self.set_f(rd, float(f32(self.f(a)) * f32(self.f(b)))),
+            Instrution::Fdiv(rd, a, b) => self.set_f(rd, float(f32(self.f(a)) / f32(self.f(b)))),
+            Instrution::Beq(a, b, target) => next = branch(self.x(a) == self.x(b), target),
+            Instrution::Bne(a, b, target) => next = branch(self.x(a) != self.x(b), target),
+            Instrution::Blt(a, b, target) => next = branch(self.x(a) < self.x(b), target),
+            Instrution::Bge(a, b, target) => next = branch(self.x(a) >= self.x(b), target),
+            Instrution::Jal(rd, target) => {
+                self.set_x(rd, pc as i32 + 1);
+                next = target;
+            }
+            Instrution::Jalr(rd, imm, rs) => {
+                next = self.x(rs).wrapping_add(imm) as u32;
+                self.set_x(rd, pc as i32 + 1);
+            }
+        }
+        self.pc = next;
+        self.retired += 1;
+        Ok(())
+    }
+
+    /// Run to the end of the program, failing after `budget` instructions.
+    pub fn run(&mut self, budget: u64) -> Result<u64> {
+        let start = self.retired;
+        while !self.is_done() {
+            if self.retired - start >= budget {
+                return Err(anyhow!(
+                    "Golden model did not finish within {} instructions",
+                    budget
+                ));
+            }
+            self.step()?;
+        }
+        Ok(self.retired - start)
+    }
+}
+
+/// A piece of architectural state.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Location {
+    Reg(RegIndex),
+    FReg(RegIndex),
+    Mem(i32),
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Reg(r) => write!(f, "x{}", r),
+            Location::FReg(r) => write!(f, "f{}", r),
+            Location::Mem(addr) => write!(f, "mem[{:#06x}]", addr),
+        }
+    }
+}
+
+/// State where the out-of-order machine and the golden model disagree.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct Divergence {
+    pub location: Location,
+    pub expected: i32,
+    pub actual: i32,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, found {}",
+            self.location, self.expected, self.actual
+        )
+    }
+}
+
+/// Compare the architectural state of `t` with `golden`: both register
+/// files, then memory word by word.
+pub fn compare(t: &Tomasulo, golden: &Golden) -> Vec<Divergence> {
+    let regs = t.rg.regs.iter().zip(golden.rg.regs.iter());
+    let fregs = t.rg.fregs.iter().zip(golden.rg.fregs.iter());
+    let words = t.mem.words().zip(golden.mem.words());
+    regs.enumerate()
+        .map(|(i, (a, e))| (Location::Reg(RegIndex(i as u8)), e.value, a.value))
+        .chain(
+            fregs
+                .enumerate()
+                .map(|(i, (a, e))| (Location::FReg(RegIndex(i as u8)), e.value, a.value)),
+        )
+        .chain(words.map(|((addr, a), (_, e))| (Location::Mem(addr), e, a)))
+        .filter(|(_, expected, actual)| expected != actual)
+        .map(|(location, expected, actual)| Divergence {
+            location,
+            expected,
+            actual,
+        })
+        .collect()
+}
+
+/// Run the program loaded in `t` on both models and return where their
+/// final states differ. The out-of-order run fails after `cycles` cycles and
+/// the sequential one after `instructions` instructions.
+pub fn check(t: &mut Tomasulo, cycles: u32, instructions: u64) -> Result<Vec<Divergence>> {
+    let mut golden = Golden::new(t);
+    golden.run(instructions)?;
+    t.run_to_completion(cycles)?;
+    Ok(compare(t, &golden))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, Golden, Location};
+    use crate::comp::config::MachineConfig;
+    use crate::comp::reg::RegIndex;
+    use crate::comp::Tomasulo;
+
+    const PROGRAM: &str = "addi x1 x0 4\naddi x3 x0 0\nloop: lw x2 0(x3)\nmul x2 x2 x1\nsw x2 16(x3)\naddi x3 x3 4\naddi x1 x1 -1\nbne x1 x0 loop\njal x5 end\naddi x6 x0 1\nend: flw f1 16(x0)\nfadd.s f2 f1 f1";
+
+    #[test]
+    fn sequential() {
+        let mut t = Tomasulo {
+            config: MachineConfig {
+                mem_size: 64,
+                mem_words: vec![1, 2, 3, 4],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        t.init_instruction(PROGRAM).unwrap();
+        let mut golden = Golden::new(&t);
+        assert_eq!(golden.run(100).unwrap(), 29);
+        assert_eq!(golden.mem.load_word(16).unwrap(), 4);
+        assert_eq!(golden.mem.load_word(28).unwrap(), 4);
+        assert_eq!(golden.rg.get_reg(RegIndex(5)).value, 9);
+        assert_eq!(golden.rg.get_reg(RegIndex(6)).value, 6);
+        assert!(Golden::new(&t).run(10).is_err());
+    }
+
+    #[test]
+    fn machines_agree() {
+        let configs = ["", "rob = 2\ncdb = 2\ndisambiguation = \"speculative\""];
+        for config in configs {
+            let mut t = Tomasulo {
+                config: MachineConfig::from_toml(&format!(
+                    "{}\nmem_size = 64\nmem_words = [1, 2, 3, 4]",
+                    config
+                ))
+                .unwrap(),
+                ..Default::default()
+            };
+            t.init_instruction(PROGRAM).unwrap();
+            assert_eq!(check(&mut t, 1000, 100).unwrap(), vec![]);
+        }
+        let mut t = Tomasulo::default();
+        t.init_instruction("addi x1 x0 1").unwrap();
+        let mut golden = Golden::new(&t);
+        golden.run(10).unwrap();
+        t.run_to_completion(10).unwrap();
+        t.rg.regs[1].value = 2;
+        let divergences = super::compare(&t, &golden);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].location, Location::Reg(RegIndex(1)));
+        assert_eq!(divergences[0].to_string(), "x1: expected 1, found 2");
+    }
+}
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use super::rob::RobTag;
+
+pub const DEFAULT_LSQ_SIZE: usize = 8;
+
+/// How loads treat older stores whose address is still unknown.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Disambiguation {
+    /// Wait until every older store address is known.
+    #[default]
+    Conservative,
+    /// Assume no conflict and replay the load if an older store turns out
+    /// to write the word it read.
+    Speculative,
+}
+
+/// How a load gets its value.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Access {
+    /// Blocked by an older store.
+    Wait,
+    /// From the data of an older store still in the queue.
+    Forward(RobTag, i32),
+    /// From memory.
+    Memory,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LsqEntry {
+    pub tag: RobTag,
+    pub store: bool,
+    /// Effective address, known after the address stage.
+    pub addr: Option<i32>,
+    /// Value to store, or value loaded once the load has accessed memory.
+    pub data: Option<i32>,
+    /// Store a load was forwarded from.
+    pub source: Option<RobTag>,
+    /// The load read its value.
+    pub performed: bool,
+}
+
+/// Loads and stores in program order, from issue until they commit.
+#[derive(Clone, Debug)]
+pub struct Lsq {
+    pub entries: VecDeque<LsqEntry>,
+    pub capacity: usize,
+    pub mode: Disambiguation,
+    /// Loads served by an older store.
+    pub forwards: u64,
+    /// Loads that read a stale value and had to be replayed.
+    pub replays: u64,
+}
+
+impl Default for Lsq {
+    fn default() -> Self {
+        Self::new(DEFAULT_LSQ_SIZE, Disambiguation::default())
+    }
+}
+
+/// Whether two word accesses touch a common byte.
+fn overlap(a: i32, b: i32) -> bool {
+    a.abs_diff(b) < 4
+}
+
+impl Lsq {
+    pub fn new(capacity: usize, mode: Disambiguation) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+            mode,
+            forwards: 0,
+            replays: 0,
+        }
+    }
+
+    /// Drop every entry, used on a flush.
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= self.capacity
+    }
+
+    pub fn push(&mut self, tag: RobTag, store: bool) {
+        self.entries.push_back(LsqEntry {
+            tag,
+            store,
+            addr: None,
+            data: None,
+            source: None,
+            performed: false,
+        });
+    }
+
+    fn position(&self, tag: RobTag) -> Option<usize> {
+        self.entries.iter().position(|v| v.tag == tag)
+    }
+
+    pub fn get(&self, tag: RobTag) -> Option<&LsqEntry> {
+        self.entries.iter().find(|v| v.tag == tag)
+    }
+
+    /// Remove the oldest entry when `tag`, the instruction committing, is it.
+    pub fn retire(&mut self, tag: RobTag) {
+        if self.entries.front().is_some_and(|v| v.tag == tag) {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Record the effective address of `tag`. When a store address arrives
+    /// after a younger load to the same word already read a value that did
+    /// not come from this store or a younger one, that load is returned so
+    /// it can be replayed.
+    pub fn set_addr(&mut self, tag: RobTag, addr: i32) -> Option<RobTag> {
+        let i = self.position(tag)?;
+        self.entries[i].addr = Some(addr);
+        if !self.entries[i].store {
+            return None;
+        }
+        let stale = self
+            .entries
+            .iter()
+            .skip(i + 1)
+            .find(|v| {
+                !v.store
+                    && v.performed
+                    && v.addr.is_some_and(|a| overlap(a, addr))
+                    && v.source.is_none_or(|s| self.position(s) < Some(i))
+            })?
+            .tag;
+        self.replays += 1;
+        Some(stale)
+    }
+
+    pub fn set_data(&mut self, tag: RobTag, data: i32) {
+        if let Some(i) = self.position(tag) {
+            self.entries[i].data = Some(data);
+        }
+    }
+
+    /// Where the load `tag` can get its value from now. The youngest older
+    /// store to the same word wins; a store to an overlapping but different
+    /// address blocks the load until it commits.
+    pub fn access(&self, tag: RobTag) -> Access {
+        let Some(i) = self.position(tag) else {
+            return Access::Wait;
+        };
+        let Some(addr) = self.entries[i].addr else {
+            return Access::Wait;
+        };
+        for store in self.entries.range(..i).rev().filter(|v| v.store) {
+            match store.addr {
+                None if self.mode == Disambiguation::Conservative => return Access::Wait,
+                None => {}
+                Some(a) if a == addr => {
+                    return store
+                        .data
+                        .map_or(Access::Wait, |v| Access::Forward(store.tag, v))
+                }
+                Some(a) if overlap(a, addr) => return Access::Wait,
+                Some(_) => {}
+            }
+        }
+        Access::Memory
+    }
+
+    /// The load `tag` read `value`, from the store `source` if forwarded.
+    pub fn perform(&mut self, tag: RobTag, value: i32, source: Option<RobTag>) {
+        if let Some(i) = self.position(tag) {
+            let entry = &mut self.entries[i];
+            entry.data = Some(value);
+            entry.source = source;
+            entry.performed = true;
+            self.forwards += source.is_some() as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Access, Disambiguation, Lsq};
+
+    #[test]
+    fn disambiguation() {
+        let mut lsq = Lsq::new(4, Disambiguation::Conservative);
+        lsq.push(0, true);
+        lsq.push(1, true);
+        lsq.push(2, false);
+        lsq.set_addr(2, 8);
+        assert_eq!(lsq.access(2), Access::Wait);
+        lsq.set_addr(1, 8);
+        lsq.set_data(1, 5);
+        // the youngest matching store forwards even with an unknown older one
+        assert_eq!(lsq.access(2), Access::Forward(1, 5));
+        lsq.set_addr(1, 10);
+        assert_eq!(lsq.access(2), Access::Wait);
+
+        let mut lsq = Lsq::new(4, Disambiguation::Speculative);
+        lsq.push(0, true);
+        lsq.push(1, false);
+        lsq.set_addr(1, 8);
+        assert_eq!(lsq.access(1), Access::Memory);
+        lsq.perform(1, 0, None);
+        assert_eq!(lsq.set_addr(0, 8), Some(1));
+        assert_eq!(lsq.replays, 1);
+        lsq.retire(0);
+        assert_eq!(lsq.entries.len(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Result};
+use std::fmt::Display;
+
+pub const DEFAULT_MEM_SIZE: usize = 1024;
+
+/// Byte addressable data memory. Words are stored little-endian.
+#[derive(Clone, Debug)]
+pub struct Mem {
+    pub data: Vec<u8>,
+    init: Vec<u8>,
+    /// Address and previous contents of every word stored since the reset,
+    /// so that stepping backward can undo the stores.
+    undo: Vec<(usize, [u8; 4])>,
+}
+
+impl Default for Mem {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEM_SIZE)
+    }
+}
+
+impl Display for Mem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.words()
+            .try_for_each(|(addr, value)| writeln!(f, "{:#06x}\t{}", addr, value))
+    }
+}
+
+impl Mem {
+    pub fn new(size: usize) -> Self {
+        Self::with_contents(size, &[])
+    }
+
+    /// Memory of `size` bytes whose first bytes are `contents`, the rest zero.
+    pub fn with_contents(size: usize, contents: &[u8]) -> Self {
+        let mut init = vec![0; size.max(contents.len())];
+        init[..contents.len()].copy_from_slice(contents);
+        Self {
+            data: init.clone(),
+            init,
+            undo: Vec::new(),
+        }
+    }
+
+    /// Memory of `size` bytes initialised with consecutive words from address 0.
+    pub fn with_words(size: usize, words: &[i32]) -> Self {
+        let bytes = words
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<u8>>();
+        Self::with_contents(size, &bytes)
+    }
+
+    /// Restore the initial contents.
+    pub fn reset(&mut self) {
+        self.data.clone_from(&self.init);
+        self.undo.clear();
+    }
+
+    /// Number of stores that can be undone.
+    pub fn stores(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Undo the stores made after the first `count` ones.
+    pub fn rollback(&mut self, count: usize) {
+        while self.undo.len() > count {
+            let (start, word) = self.undo.pop().unwrap();
+            self.data[start..start + 4].copy_from_slice(&word);
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Every word with its address, in address order. A trailing partial
+    /// word is padded with zeros.
+    pub fn words(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.data.chunks(4).enumerate().map(|(i, v)| {
+            let mut word = [0; 4];
+            word[..v.len()].copy_from_slice(v);
+            (i as i32 * 4, i32::from_le_bytes(word))
+        })
+    }
+
+    fn range(&self, addr: i32) -> Result<std::ops::Range<usize>> {
+        let start = usize::try_from(addr).map_err(|_| anyhow!("Bad address {}", addr))?;
+        let end = start + 4;
+        if end > self.data.len() {
+            return Err(anyhow!("Address {} out of memory", addr));
+        }
+        Ok(start..end)
+    }
+
+    /// Whether the word at `addr` is inside memory.
+    pub fn check_word(&self, addr: i32) -> Result<()> {
+        self.range(addr).map(|_| ())
+    }
+
+    pub fn load_word(&self, addr: i32) -> Result<i32> {
+        let range = self.range(addr)?;
+        let mut word = [0; 4];
+        word.copy_from_slice(&self.data[range]);
+        Ok(i32::from_le_bytes(word))
+    }
+
+    pub fn store_word(&mut self, addr: i32, value: i32) -> Result<()> {
+        let range = self.range(addr)?;
+        let mut word = [0; 4];
+        word.copy_from_slice(&self.data[range.clone()]);
+        self.undo.push((range.start, word));
+        self.data[range].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Mem;
+
+    #[test]
+    fn little_endian_words() {
+        let mut mem = Mem::with_words(16, &[0x01020304]);
+        assert_eq!(mem.data[..4], [4, 3, 2, 1]);
+        mem.store_word(8, -2).unwrap();
+        assert_eq!(mem.load_word(8).unwrap(), -2);
+        assert!(mem.load_word(13).is_err());
+        assert!(mem.load_word(-4).is_err());
+        assert_eq!(
+            mem.words().collect::<Vec<_>>(),
+            [(0, 0x01020304), (4, 0), (8, -2), (12, 0)]
+        );
+        mem.store_word(8, 7).unwrap();
+        mem.rollback(1);
+        assert_eq!(mem.load_word(8).unwrap(), -2);
+        assert_eq!(mem.stores(), 1);
+        mem.reset();
+        assert_eq!(mem.load_word(8).unwrap(), 0);
+        assert_eq!(mem.stores(), 0);
+    }
+}
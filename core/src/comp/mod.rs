@@ -1,45 +1,970 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
 
-use crate::comp::pc::Instrution;
-use crate::comp::pc::PC;
-use crate::comp::reg::REG_GROUP;
+use crate::comp::config::MachineConfig;
+use crate::comp::mem::Mem;
+use crate::comp::pc::{Instrution, Pc};
+use crate::comp::predictor::BranchPredictor;
+use crate::comp::reg::RegGroup;
+use crate::comp::rename::{PhysRegFile, Renamer, Renaming};
+use crate::comp::rob::{InstrStatus, Rob, RobTag};
+use crate::comp::snapshot::{Snapshot, Snapshots};
+use crate::comp::stats::Stats;
+use crate::comp::trace::{Event, Subscriber, Subscribers};
 
-use self::rs::RS;
+use self::rs::{Rs, StallReason};
+pub mod asm;
+pub mod cdb;
+pub mod config;
+pub mod dataflow;
+pub mod fu;
+#[cfg(test)]
+mod fuzz;
+pub mod golden;
+pub mod lsq;
+pub mod mem;
 pub mod pc;
+pub mod predictor;
 pub mod reg;
+pub mod rename;
+pub mod rob;
 pub mod rs;
+pub mod snapshot;
+pub mod stats;
+pub mod trace;
 
-#[derive(Default)]
-pub struct Tomasulo {}
+/// What happened during one clock cycle. Instructions in flight are
+/// identified by their ROB tag.
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct CycleReport {
+    pub cycle: u32,
+    pub issued: Option<RobTag>,
+    /// Instructions that began executing.
+    pub started: Vec<RobTag>,
+    /// Instructions whose execution finished.
+    pub completed: Vec<RobTag>,
+    /// Results put on the CDB.
+    pub broadcast: Vec<(RobTag, i32)>,
+    /// Finished results that lost CDB arbitration.
+    pub cdb_wait: Vec<RobTag>,
+    pub committed: Option<InstrStatus>,
+    /// Why nothing was issued although instructions were left to fetch.
+    pub stall: Option<StallReason>,
+    /// Everything above and more, in the order it happened.
+    pub events: Vec<Event>,
+}
+
+/// A single simulator instance. All machine state is owned here, so several
+/// independent simulations can live side by side in one process.
+#[derive(Default, Clone)]
+pub struct Tomasulo {
+    pub config: MachineConfig,
+    pub rs: Rs,
+    pub rg: RegGroup,
+    /// Physical registers when renaming with them, in which case `rg` only
+    /// receives committed values.
+    pub prf: Option<PhysRegFile>,
+    pub pc: Pc,
+    pub mem: Mem,
+    pub rob: Rob,
+    pub predictor: Box<dyn BranchPredictor>,
+    /// Cycles simulated since the program was loaded.
+    cycle: u32,
+    /// Instructions that left the ROB, in program order.
+    pub history: Vec<InstrStatus>,
+    /// Earlier states, for stepping backward.
+    pub snapshots: Snapshots,
+    /// Cycles in which issue stalled, per reason.
+    pub stalls: BTreeMap<StallReason, u32>,
+    /// Utilization of every cycle simulated so far.
+    pub stats: Stats,
+    /// Receivers of the events of each cycle. Cycles replayed by
+    /// [`Self::goto_cycle`] are published again.
+    pub subscribers: Subscribers,
+}
 
 impl Tomasulo {
-    pub fn init_instruction(&self, instr: &str) -> Result<()> {
-        let mut rs = RS.write().unwrap();
-        rs.reset();
-        let mut rg = REG_GROUP.write().unwrap();
-        rg.reset();
-        let mut pc = PC.write().unwrap();
-        let instrs = instr
-            .split('\n')
-            .filter(|v| v.len() > 2)
-            .map(|v| v.into())
-            .collect::<Vec<Instrution>>();
-        pc.reset_with_instrutions(instrs);
-        println!("{}", pc);
+    /// Load a program and size every structure from `config`. Assembler
+    /// errors are returned as [`asm::Diagnostics`].
+    pub fn init_instruction(&mut self, instr: &str) -> Result<()> {
+        let instrs = asm::assemble(instr, self.config.regs)?;
+        self.load(instrs)
+    }
+    /// Load an already assembled program, like [`Self::init_instruction`].
+    pub fn load(&mut self, instrs: Vec<Instrution>) -> Result<()> {
+        self.config.validate()?;
+        let regs = instrs
+            .iter()
+            .flat_map(|v| v.dest().into_iter().chain(v.sources()));
+        if let Some(reg) = regs
+            .max_by_key(|v| v.0)
+            .filter(|v| v.index() >= self.config.regs)
+        {
+            return Err(anyhow!(
+                "Register {} is out of range, the machine has {} registers",
+                reg,
+                self.config.regs
+            ));
+        }
+        self.rs = Rs::new(&self.config);
+        self.rg = RegGroup::new(self.config.regs);
+        self.prf = (self.config.renaming == Renaming::PhysicalRegisters)
+            .then(|| PhysRegFile::new(self.config.physical_regs, &self.rg));
+        self.rob = Rob::new(self.config.rob);
+        self.predictor = self.config.predictor.build(self.config.predictor_entries);
+        self.mem = Mem::with_words(self.config.mem_size, &self.config.mem_words);
+        self.cycle = 0;
+        self.history.clear();
+        self.stalls.clear();
+        self.stats = Stats::default();
+        self.pc.reset_with_instrutions(instrs);
+        self.snapshots.reset(Snapshot::capture(self));
+        Ok(())
+    }
+    /// Current clock, the number of cycles simulated since loading.
+    pub fn cycle(&self) -> u32 {
+        self.cycle
+    }
+    /// Whether every instruction has been fetched and retired.
+    pub fn is_drained(&self) -> bool {
+        self.pc.is_done() && self.rob.is_empty()
+    }
+    /// Advance the machine by one clock cycle. A cycle that fails leaves the
+    /// machine as it was.
+    pub fn step(&mut self) -> Result<CycleReport> {
+        self.rob.check_commit(&self.mem)?;
+        if self.snapshots.capacity > 0 {
+            self.snapshots.push(Snapshot::capture(self));
+        }
+        self.cycle += 1;
+        let mut report = CycleReport {
+            cycle: self.cycle,
+            ..Default::default()
+        };
+        let head = self.rob.head;
+        if let Some(load) = self.rob.head().filter(|v| v.ready() && v.replay) {
+            // the load read a stale value, fetch it again
+            let pc = load.pc;
+            self.flush(pc);
+            report.events.push(Event::Flush { pc });
+        } else if let Some(entry) = self.rob.commit(&mut self.rg, &mut self.mem)? {
+            self.rs.lsq.retire(head);
+            if let (Some(prf), Some(reg)) = (&mut self.prf, entry.dest) {
+                prf.commit(reg, entry.fp, head);
+            }
+            report.events.push(Event::Commit {
+                tag: head,
+                pc: entry.pc,
+                instr: entry.instr.unwrap(),
+            });
+            // x0 is never written
+            if let Some(reg) = entry.dest.filter(|v| entry.fp || v.0 != 0) {
+                report.events.push(Event::RegisterWrite {
+                    tag: head,
+                    reg,
+                    fp: entry.fp,
+                    value: entry.value.unwrap(),
+                });
+            }
+            let mut status = InstrStatus::from(&entry);
+            status.timing.commit = Some(self.cycle);
+            self.history.push(status);
+            report.committed = Some(status);
+            if entry.instr.is_some_and(|v| v.is_branch()) {
+                self.predictor
+                    .update(entry.pc, entry.target != Some(entry.pc + 1));
+            }
+            if entry.mispredicted() {
+                self.flush(entry.target.unwrap());
+                report.events.push(Event::Flush {
+                    pc: entry.target.unwrap(),
+                });
+            }
+        }
+        self.rs.update(&mut self.rob, &self.mem, &mut report);
+        if let Some(prf) = &mut self.prf {
+            report
+                .broadcast
+                .iter()
+                .for_each(|&(tag, value)| prf.write(tag, value));
+        }
+        if !self.config.speculation {
+            // nothing was issued past a resolved branch, fetch from its target
+            // right away instead of flushing at commit
+            let youngest = self.rob.iter().last().filter(|(_, v)| v.mispredicted());
+            if let Some((tag, target)) = youngest.map(|(tag, v)| (tag, v.target.unwrap())) {
+                self.rob.get_mut(tag).pred = target;
+                self.pc.index = target;
+            }
+        }
+        let issued =
+            if !self.config.speculation && !self.pc.is_done() && self.rob.has_unresolved_branch() {
+                Err(StallReason::BranchPending)
+            } else {
+                let renamer: &mut dyn Renamer = match &mut self.prf {
+                    Some(prf) => prf,
+                    None => &mut self.rg,
+                };
+                self.pc.run(
+                    &mut self.rs,
+                    renamer,
+                    &mut self.rob,
+                    self.predictor.as_ref(),
+                    self.cycle,
+                )
+            };
+        match issued {
+            Ok(None) => {}
+            Ok(Some(tag)) => {
+                let entry = self.rob.get(tag);
+                report.issued = Some(tag);
+                report.events.push(Event::Issue {
+                    tag,
+                    pc: entry.pc,
+                    instr: entry.instr.unwrap(),
+                });
+            }
+            Err(reason) => {
+                report.stall = Some(reason);
+                *self.stalls.entry(reason).or_default() += 1;
+                report.events.push(Event::Stall { reason });
+            }
+        }
+        self.stats.record(&self.rs, &self.rob, &report);
+        self.subscribers.publish(self.cycle, &report.events)?;
+        Ok(report)
+    }
+    /// Publish the events of every later cycle to `subscriber`.
+    pub fn subscribe(&mut self, subscriber: impl Subscriber + 'static) {
+        self.subscribers.0.push(Box::new(subscriber));
+    }
+    /// Go back one cycle.
+    pub fn step_back(&mut self) -> Result<()> {
+        if self.cycle == 0 {
+            return Err(anyhow!("Already at cycle 0"));
+        }
+        self.goto_cycle(self.cycle - 1)
+    }
+    /// Move the clock to cycle `n`, restoring a snapshot when going back
+    /// and stepping when going forward. Cycles older than the snapshot ring
+    /// are reached by replaying from the loaded program.
+    pub fn goto_cycle(&mut self, n: u32) -> Result<()> {
+        self.rewind(n)?;
+        while self.cycle < n {
+            self.step()?;
+        }
+        Ok(())
+    }
+    /// Go back to cycle `n` from the snapshots, or to the newest one older
+    /// than it, which is the loaded program at worst. Never steps forward.
+    pub fn rewind(&mut self, n: u32) -> Result<()> {
+        while self.cycle > n {
+            match self.snapshots.pop() {
+                Some(snapshot) => snapshot.restore(self),
+                None => break,
+            }
+        }
+        if self.cycle > n {
+            self.snapshots
+                .origin()
+                .cloned()
+                .ok_or(anyhow!("No program loaded"))?
+                .restore(self);
+        }
         Ok(())
     }
-    fn step(&self) {
-        let mut pc = PC.write().unwrap();
-        let mut rs = RS.write().unwrap();
-        rs.update();
-        drop(rs);
-        let _ = pc.run();
-        let rs = RS.read().unwrap();
-        println!("{}", rs);
+    /// Squash everything younger than a mispredicted branch that just
+    /// committed and restart fetching at `target`.
+    fn flush(&mut self, target: u32) {
+        self.rs.reset();
+        let cycle = self.cycle;
+        self.rob.flush().iter().for_each(|v| {
+            let mut status = InstrStatus {
+                squashed: true,
+                ..v.into()
+            };
+            status.timing.squash = Some(cycle);
+            self.history.push(status);
+        });
+        self.rg.clear_states();
+        if let Some(prf) = &mut self.prf {
+            prf.flush();
+        }
+        self.pc.index = target;
     }
-    pub fn run_to(&self, i: i32) {
+    /// The register state issue reads and renames.
+    pub fn renamer(&self) -> &dyn Renamer {
+        match &self.prf {
+            Some(prf) => prf,
+            None => &self.rg,
+        }
+    }
+    /// Every dynamic instruction issued so far in program order: those that
+    /// committed or were squashed, then those still in the ROB.
+    pub fn instr_status(&self) -> Vec<InstrStatus> {
+        self.instr_status_iter().collect()
+    }
+    /// [`Self::instr_status`] without collecting it. Skipping ahead is cheap,
+    /// so a view can build only the rows it shows.
+    pub fn instr_status_iter(&self) -> impl Iterator<Item = InstrStatus> + '_ {
+        self.history
+            .iter()
+            .copied()
+            .chain(self.rob.iter().map(|(_, v)| v.into()))
+    }
+    /// Length of [`Self::instr_status`].
+    pub fn instr_count(&self) -> usize {
+        self.history.len() + self.rob.count
+    }
+    pub fn run_to(&mut self, i: i32) -> Result<()> {
         for _ in 0..i {
-            self.step();
+            self.step()?;
+        }
+        Ok(())
+    }
+    /// Step until `predicate` holds or the program has drained, returning
+    /// the number of cycles run. A program that loops forever never drains,
+    /// so the predicate should bound the run.
+    pub fn run_until(&mut self, mut predicate: impl FnMut(&Self) -> bool) -> Result<u32> {
+        let start = self.cycle;
+        while !predicate(self) && !self.is_drained() {
+            self.step()?;
+        }
+        Ok(self.cycle - start)
+    }
+    /// Run until the program has drained, failing if that takes more than
+    /// `budget` cycles. Returns the number of cycles run.
+    pub fn run_to_completion(&mut self, budget: u32) -> Result<u32> {
+        let end = self.cycle + budget;
+        let cycles = self.run_until(|t| t.cycle >= end)?;
+        if !self.is_drained() {
+            return Err(anyhow!("Program did not finish within {} cycles", budget));
+        }
+        Ok(cycles)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Tomasulo;
+    use crate::comp::config::MachineConfig;
+    use crate::comp::pc::Instrution;
+    use crate::comp::reg::RegIndex;
+    use crate::comp::rename::Renaming;
+    use crate::comp::rob::RobState;
+    use crate::comp::rs::{RsType, StallReason};
+    use crate::comp::trace::{Event, Subscriber};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn independent_instances() {
+        let mut a = Tomasulo::default();
+        let mut b = Tomasulo::default();
+        a.init_instruction("add x1 x2 x3").unwrap();
+        b.init_instruction("sub x1 x2 x3").unwrap();
+        a.run_to(5).unwrap();
+        b.run_to(5).unwrap();
+        assert_eq!(a.rg.get_reg(RegIndex(1)).value, 5);
+        assert_eq!(b.rg.get_reg(RegIndex(1)).value, -1);
+    }
+
+    #[test]
+    fn load_store() {
+        let mut t = Tomasulo {
+            config: MachineConfig {
+                mem_size: 64,
+                mem_words: vec![0, 0, 0, 42],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        t.init_instruction("lw x1 12 x0\nsw x1 12 x8").unwrap();
+        t.run_to(20).unwrap();
+        assert_eq!(t.rg.get_reg(RegIndex(1)).value, 42);
+        assert_eq!(t.mem.load_word(20).unwrap(), 42);
+    }
+
+    #[test]
+    fn in_order_commit() {
+        let mut t = Tomasulo::default();
+        t.init_instruction("mul x1 x2 x3\nadd x4 x2 x3").unwrap();
+        t.run_to(6).unwrap();
+        // add has finished but must wait for the older mul to commit
+        assert!(t.rob.get(1).ready());
+        assert_eq!(t.rg.get_reg(RegIndex(4)).value, 4);
+        assert_eq!(t.rg.get_reg(RegIndex(4)).state, Some(1));
+        t.run_to(14).unwrap();
+        assert!(t.rob.is_empty());
+        assert_eq!(t.rg.get_reg(RegIndex(1)).value, 6);
+        assert_eq!(t.rg.get_reg(RegIndex(4)).value, 5);
+    }
+
+    #[test]
+    fn loop_with_mispredict() {
+        let mut t = Tomasulo::default();
+        t.init_instruction(
+            "add x1 x0 x0\nloop: add x1 x1 x2\nblt x1 x10 loop\nadd x20 x1 x0\nbeq x0 x0 end\nadd x20 x0 x0\nend:",
+        )
+        .unwrap();
+        t.run_to(100).unwrap();
+        assert_eq!(t.rg.get_reg(RegIndex(1)).value, 10);
+        assert_eq!(t.rg.get_reg(RegIndex(20)).value, 10);
+    }
+
+    #[test]
+    fn predictor_per_load() {
+        let program = "add x1 x0 x0\nloop: add x1 x1 x2\nblt x1 x10 loop\nadd x20 x1 x0";
+        let squashed = |t: &Tomasulo| t.history.iter().filter(|v| v.squashed).count();
+        let mut t = Tomasulo::default();
+        t.init_instruction(program).unwrap();
+        t.run_to_completion(200).unwrap();
+        let first = squashed(&t);
+        // a second load starts from untrained counters
+        t.init_instruction(program).unwrap();
+        t.run_to_completion(200).unwrap();
+        assert_eq!(squashed(&t), first);
+
+        t.config = MachineConfig::from_toml("predictor = \"taken\"").unwrap();
+        t.init_instruction(program).unwrap();
+        assert_eq!(t.predictor.name(), "taken");
+        t.run_to_completion(200).unwrap();
+        assert_eq!(t.rg.get_reg(RegIndex(20)).value, 10);
+    }
+
+    #[test]
+    fn jal_jalr() {
+        let mut t = Tomasulo::default();
+        t.init_instruction(
+            "jal x1 func\nadd x5 x1 x0\njal x0 end\nfunc: add x6 x2 x3\njalr x0 0 x1\nend:",
+        )
+        .unwrap();
+        t.run_to(60).unwrap();
+        assert_eq!(t.rg.get_reg(RegIndex(1)).value, 1);
+        assert_eq!(t.rg.get_reg(RegIndex(5)).value, 1);
+        assert_eq!(t.rg.get_reg(RegIndex(6)).value, 5);
+        assert!(t.rob.is_empty());
+    }
+
+    #[test]
+    fn divide() {
+        let mut t = Tomasulo::default();
+        t.config.latency.div = 5;
+        t.config.latency.rem = 5;
+        t.init_instruction("div x1 x10 x3\nrem x2 x10 x3\ndiv x4 x10 x0\nrem x5 x10 x0")
+            .unwrap();
+        t.run_to(40).unwrap();
+        assert!(t.rob.is_empty());
+        assert_eq!(t.rg.get_reg(RegIndex(1)).value, 3);
+        assert_eq!(t.rg.get_reg(RegIndex(2)).value, 1);
+        assert_eq!(t.rg.get_reg(RegIndex(4)).value, -1);
+        assert_eq!(t.rg.get_reg(RegIndex(5)).value, 10);
+    }
+
+    #[test]
+    fn sized_from_config() {
+        let mut t = Tomasulo {
+            config: MachineConfig::from_toml(
+                "regs = 8\nrob = 4\ncdb = 2\n[units]\nload = 1\nstore = 1\nadd = 3\nmul = 1",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        assert!(t.init_instruction("add x9 x1 x2").is_err());
+        assert!(t
+            .load(vec![Instrution::Addi(RegIndex(1), RegIndex(8), 0)])
+            .is_err());
+        t.init_instruction("add x1 x2 x3\nadd x4 x5 x6\nsub x7 x6 x5")
+            .unwrap();
+        assert_eq!(t.rg.regs.len(), 8);
+        assert_eq!(t.rob.size(), 4);
+        t.run_to(4).unwrap();
+        // with three add units the younger adds do not wait for the first
+        assert!(t.rob.get(0).ready());
+        assert_eq!(t.rob.get(1).state, RobState::Execute);
+        assert_eq!(t.rob.get(2).state, RobState::Execute);
+        t.run_to(4).unwrap();
+        assert!(t.rob.is_empty());
+        assert_eq!(t.rg.get_reg(RegIndex(7)).value, 1);
+    }
+
+    #[test]
+    fn cycle_reports() {
+        let mut t = Tomasulo::default();
+        t.init_instruction("add x1 x2 x3\nadd x4 x1 x1").unwrap();
+        let report = t.step().unwrap();
+        assert_eq!(report.cycle, 1);
+        assert_eq!(report.issued, Some(0));
+        let report = t.step().unwrap();
+        assert_eq!(report.started, vec![0]);
+        assert_eq!(report.issued, Some(1));
+        let report = t.step().unwrap();
+        assert_eq!(report.completed, vec![0]);
+        let report = t.step().unwrap();
+        assert_eq!(report.broadcast, vec![(0, 5)]);
+        assert_eq!(t.run_until(|t| t.rob.is_empty()).unwrap(), 4);
+        assert!(t.is_drained());
+        assert_eq!(t.cycle(), 8);
+        assert_eq!(t.rg.get_reg(RegIndex(4)).value, 10);
+    }
+
+    #[test]
+    fn event_stream() {
+        struct Recorder(Rc<RefCell<Vec<(u32, Event)>>>);
+        impl Subscriber for Recorder {
+            fn event(&mut self, cycle: u32, event: &Event) -> anyhow::Result<()> {
+                self.0.borrow_mut().push((cycle, event.clone()));
+                Ok(())
+            }
         }
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut t = Tomasulo::default();
+        t.subscribe(Recorder(events.clone()));
+        t.init_instruction("add x1 x2 x3").unwrap();
+        t.run_to_completion(10).unwrap();
+        let instr = t.history[0].instr;
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                (
+                    1,
+                    Event::Issue {
+                        tag: 0,
+                        pc: 0,
+                        instr
+                    }
+                ),
+                (
+                    2,
+                    Event::Dispatch {
+                        tag: 0,
+                        class: RsType::Add,
+                        unit: 0
+                    }
+                ),
+                (
+                    2,
+                    Event::ExecTick {
+                        tag: 0,
+                        remaining: 1
+                    }
+                ),
+                (
+                    3,
+                    Event::ExecTick {
+                        tag: 0,
+                        remaining: 0
+                    }
+                ),
+                (3, Event::Complete { tag: 0 }),
+                (4, Event::Broadcast { tag: 0, value: 5 }),
+                (
+                    5,
+                    Event::Commit {
+                        tag: 0,
+                        pc: 0,
+                        instr
+                    }
+                ),
+                (
+                    5,
+                    Event::RegisterWrite {
+                        tag: 0,
+                        reg: RegIndex(1),
+                        fp: false,
+                        value: 5
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_to_completion() {
+        let mut t = Tomasulo::default();
+        t.init_instruction("mul x1 x2 x3\nmul x4 x5 x6\nmul x7 x5 x6")
+            .unwrap();
+        let stalls = (0..5)
+            .filter_map(|_| t.step().unwrap().stall)
+            .collect::<Vec<_>>();
+        // both mul stations are taken from cycle 3 on
+        assert_eq!(stalls, vec![StallReason::NoStation(RsType::Mul); 3]);
+        assert_eq!(t.stalls[&StallReason::NoStation(RsType::Mul)], 3);
+        assert!(t.run_to_completion(5).is_err());
+        assert!(t.run_to_completion(100).unwrap() > 0);
+        assert!(t.is_drained());
+
+        let mut t = Tomasulo::default();
+        t.init_instruction("loop: beq x0 x0 loop").unwrap();
+        assert!(t.run_to_completion(50).is_err());
+        assert_eq!(t.cycle(), 50);
+    }
+
+    #[test]
+    fn without_speculation() {
+        let program = "add x1 x0 x0\nloop: add x1 x1 x2\nblt x1 x10 loop\nadd x20 x1 x0";
+        let mut t = Tomasulo {
+            config: MachineConfig {
+                speculation: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        t.init_instruction(program).unwrap();
+        t.run_to_completion(200).unwrap();
+        assert_eq!(t.rg.get_reg(RegIndex(20)).value, 10);
+        // nothing is fetched down a wrong path
+        assert!(t.history.iter().all(|v| !v.squashed));
+        assert!(t.stalls[&StallReason::BranchPending] > 0);
+
+        let mut speculative = Tomasulo::default();
+        speculative.init_instruction(program).unwrap();
+        speculative.run_to_completion(200).unwrap();
+        assert!(!speculative.stalls.contains_key(&StallReason::BranchPending));
+        // the counters follow the clock backward
+        speculative.goto_cycle(0).unwrap();
+        assert!(speculative.stalls.is_empty());
+    }
+
+    #[test]
+    fn reverse_step() {
+        let program = "lw x1 4 x0\nloop: add x1 x1 x2\nblt x1 x10 loop\nsw x1 0 x0";
+        let mut a = Tomasulo::default();
+        a.init_instruction(program).unwrap();
+        a.run_to(30).unwrap();
+        a.step_back().unwrap();
+        a.goto_cycle(12).unwrap();
+        let mut b = Tomasulo::default();
+        b.init_instruction(program).unwrap();
+        b.run_to(12).unwrap();
+        assert_eq!(a.cycle(), 12);
+        assert_eq!(a.rg.regs, b.rg.regs);
+        assert_eq!(a.pc.index, b.pc.index);
+        assert_eq!(a.mem.data, b.mem.data);
+        assert_eq!(a.history.len(), b.history.len());
+        let timing = |t: &Tomasulo| {
+            t.instr_status()
+                .iter()
+                .map(|v| v.timing)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(timing(&a), timing(&b));
+        a.goto_cycle(40).unwrap();
+        b.run_to(28).unwrap();
+        assert_eq!(timing(&a), timing(&b));
+        assert!(a.goto_cycle(0).is_ok());
+        assert!(a.step_back().is_err());
+    }
+
+    #[test]
+    fn replay_past_ring() {
+        let mut t = Tomasulo::default();
+        t.snapshots.capacity = 4;
+        t.init_instruction("mul x1 x2 x3\nadd x4 x1 x1").unwrap();
+        t.run_to(20).unwrap();
+        assert_eq!(t.snapshots.len(), 4);
+        t.goto_cycle(3).unwrap();
+        assert_eq!(t.cycle(), 3);
+        assert_eq!(t.rob.count, 2);
+        assert_eq!(t.rg.get_reg(RegIndex(1)).state, Some(0));
+    }
+
+    #[test]
+    fn cdb_contention() {
+        let write_results = |policy: &str| {
+            let mut t = Tomasulo {
+                config: MachineConfig::from_toml(&format!(
+                    "arbitration = \"{}\"\n[latency]\nmul = 3",
+                    policy
+                ))
+                .unwrap(),
+                ..Default::default()
+            };
+            t.init_instruction("mul x1 x2 x3\nadd x4 x2 x3").unwrap();
+            t.run_to(4).unwrap();
+            // both finish at 4 and ask for the single bus at 5
+            let report = t.step().unwrap();
+            assert_eq!(report.broadcast.len(), 1);
+            assert_eq!(report.cdb_wait.len(), 1);
+            t.run_to_completion(10).unwrap();
+            assert_eq!(t.rs.cdb.stats.conflicts, 1);
+            let status = t.instr_status();
+            (status[0].timing.write_result, status[1].timing.write_result)
+        };
+        assert_eq!(write_results("class_priority"), (Some(6), Some(5)));
+        assert_eq!(write_results("oldest_first"), (Some(5), Some(6)));
+    }
+
+    #[test]
+    fn pipelined_units() {
+        let starts = |pipelined: bool| {
+            let mut t = Tomasulo {
+                config: MachineConfig::from_toml(&format!(
+                    "[pipelined]\nload = false\nstore = false\nadd = {}\nmul = false\n[latency]\nadd = 3",
+                    pipelined
+                ))
+                .unwrap(),
+                ..Default::default()
+            };
+            t.init_instruction("add x1 x2 x3\nadd x4 x2 x3\nadd x7 x2 x3")
+                .unwrap();
+            t.run_to_completion(30).unwrap();
+            t.instr_status()
+                .iter()
+                .map(|v| v.timing.exec_start.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(starts(false), vec![2, 5, 8]);
+        assert_eq!(starts(true), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn store_to_load_forwarding() {
+        let mut t = Tomasulo::default();
+        t.init_instruction("sw x2 4 x0\nlw x3 4 x0\nadd x4 x3 x3")
+            .unwrap();
+        t.run_to_completion(30).unwrap();
+        assert_eq!(t.rg.get_reg(RegIndex(4)).value, 4);
+        assert_eq!(t.rs.lsq.forwards, 1);
+        let status = t.instr_status();
+        // the load reads the value before the store writes memory
+        assert!(status[1].timing.exec_start < status[0].timing.commit);
+    }
+
+    #[test]
+    fn disambiguation() {
+        // the store address depends on a long multiply
+        let program = "mul x5 x0 x0\nsw x2 0 x5\nlw x3 0 x0\nlw x6 8 x0\nadd x4 x3 x3";
+        let run = |mode: &str| {
+            let mut t = Tomasulo {
+                config: MachineConfig::from_toml(&format!("disambiguation = \"{}\"", mode))
+                    .unwrap(),
+                ..Default::default()
+            };
+            t.init_instruction(program).unwrap();
+            t.run_to_completion(100).unwrap();
+            assert_eq!(t.rg.get_reg(RegIndex(3)).value, 2);
+            assert_eq!(t.rg.get_reg(RegIndex(4)).value, 4);
+            t
+        };
+        let t = run("conservative");
+        assert_eq!(t.rs.lsq.replays, 0);
+        let status = t.instr_status();
+        assert!(status[3].timing.exec_start > status[0].timing.exec_complete);
+        let t = run("speculative");
+        assert_eq!(t.rs.lsq.replays, 1);
+        let status = t.instr_status();
+        // the load to another word went ahead of the unresolved store
+        assert!(status[3].timing.exec_start < status[0].timing.exec_complete);
+        assert!(status.iter().any(|v| v.squashed));
+    }
+
+    #[test]
+    fn invalid_config() {
+        // configurations built in code are checked like parsed ones
+        let program = vec![Instrution::Add(RegIndex(1), RegIndex(2), RegIndex(3))];
+        let configs = [
+            MachineConfig {
+                regs: 300,
+                ..Default::default()
+            },
+            MachineConfig {
+                renaming: Renaming::PhysicalRegisters,
+                physical_regs: 16,
+                ..Default::default()
+            },
+        ];
+        for config in configs {
+            let mut t = Tomasulo {
+                config,
+                ..Default::default()
+            };
+            assert!(t.load(program.clone()).is_err());
+        }
+    }
+
+    #[test]
+    fn distant_addresses() {
+        // comparing the load with the store at i32::MIN must not overflow
+        let mut t = Tomasulo::default();
+        t.init_instruction("lui x5 0x80000\nsw x2 0(x5)\nlw x3 100(x0)")
+            .unwrap();
+        let err = t.run_to_completion(100).unwrap_err();
+        assert!(err.to_string().contains("Bad address"));
+        assert_eq!(t.history.len(), 1);
+
+        // the effective address wraps around like the golden model's
+        let mut t = Tomasulo::default();
+        t.init_instruction("lui x5 0x80000\naddi x5 x5 -1\nsw x2 4(x5)")
+            .unwrap();
+        let err = t.run_to_completion(100).unwrap_err();
+        assert!(err.to_string().contains(&(i32::MIN + 3).to_string()));
+    }
+
+    #[test]
+    fn wrong_path_load_fault() {
+        // the loop runs ahead and loads past the end of memory before the
+        // last branch resolves
+        let program =
+            "addi x1 x0 0\naddi x10 x0 1024\nloop: lw x3 0(x1)\nadd x4 x4 x3\naddi x1 x1 4\nblt x1 x10 loop";
+        let mut t = Tomasulo::default();
+        t.init_instruction(program).unwrap();
+        t.run_to_completion(10_000).unwrap();
+        assert_eq!(t.rg.get_reg(RegIndex(1)).value, 1024);
+        assert!(t.history.iter().any(|v| v.squashed));
+
+        // the same load on the right path still fails, once it commits
+        let mut t = Tomasulo::default();
+        t.init_instruction("addi x1 x0 1024\nlw x3 0(x1)\nadd x4 x3 x3")
+            .unwrap();
+        let err = t.run_to_completion(100).unwrap_err();
+        assert!(err.to_string().contains("out of memory"));
+        assert!(t.history.iter().all(|v| v.instr.mnemonic() != "lw"));
+        // the failed cycle did not happen
+        let cycle = t.cycle();
+        let len = t.snapshots.len();
+        assert!(t.step().is_err());
+        assert_eq!((t.cycle(), t.snapshots.len()), (cycle, len));
+        t.step_back().unwrap();
+        assert_eq!(t.cycle(), cycle - 1);
+        assert!(t.step().is_ok());
+        assert!(t.step().is_err());
+        assert_eq!(t.cycle(), cycle);
+    }
+
+    #[test]
+    fn integer_alu() {
+        let mut t = Tomasulo::default();
+        t.init_instruction(
+            "addi x1 x0 -5\nslti x2 x1 0\nsltiu x3 x1 0\nandi x4 x1 0xff\nori x5 x0 0x7f0\nxori x6 x1 -1\nslli x7 x6 4\nsrli x8 x1 28\nsrai x9 x1 1\nslt x10 x6 x1\nsltu x11 x6 x1\nand x12 x4 x5\nor x13 x4 x5\nxor x14 x4 x5\nsll x15 x6 x6\nsrl x16 x1 x6\nsra x17 x1 x2\nlui x18 0xfffff\nauipc x19 1",
+        )
+        .unwrap();
+        t.run_to_completion(200).unwrap();
+        let regs = (1..=19)
+            .map(|i| t.rg.get_reg(RegIndex(i)).value)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            regs,
+            vec![
+                -5,
+                1,
+                0,
+                0xfb,
+                0x7f0,
+                4,
+                64,
+                15,
+                -3,
+                0,
+                1,
+                0xf0,
+                0x7fb,
+                0x70b,
+                64,
+                0x0fff_ffff,
+                -3,
+                -4096,
+                (1 << 12) + 18,
+            ]
+        );
+    }
+
+    #[test]
+    fn physical_registers() {
+        let programs = [
+            "add x1 x0 x0\nloop: add x1 x1 x2\nblt x1 x10 loop\nadd x20 x1 x0\nbeq x0 x0 end\nadd x20 x0 x0\nend:",
+            "mul x5 x0 x0\nsw x2 0 x5\nlw x3 0 x0\nlw x6 8 x0\nadd x4 x3 x3",
+            "fmul.s f1 f2 f3\nfadd.s f1 f1 f1\nfsw f1 0(x0)\nflw f4 0(x0)\njal x1 next\nnext: add x1 x1 x1",
+        ];
+        for program in programs {
+            let run = |config: &str| {
+                let mut t = Tomasulo {
+                    config: MachineConfig::from_toml(config).unwrap(),
+                    ..Default::default()
+                };
+                t.init_instruction(program).unwrap();
+                t.run_to_completion(500).unwrap();
+                t
+            };
+            let tags = run("disambiguation = \"speculative\"");
+            let physical = run(
+                "disambiguation = \"speculative\"\nrenaming = \"physical_registers\"\nphysical_regs = 66",
+            );
+            assert_eq!(tags.rg.regs, physical.rg.regs);
+            assert_eq!(tags.rg.fregs, physical.rg.fregs);
+            assert_eq!(tags.mem.data, physical.mem.data);
+            let prf = physical.prf.as_ref().unwrap();
+            // every architectural register maps to its committed value
+            assert_eq!(prf.rat, prf.retirement_rat);
+            assert_eq!(prf.free.len(), 2);
+            assert!(physical.stalls[&StallReason::NoFreeRegister] > 0);
+            assert!(prf
+                .retirement_rat
+                .iter()
+                .zip(physical.rg.regs.iter().chain(physical.rg.fregs.iter()))
+                .all(|(&p, reg)| prf.regs[p].value == reg.value));
+        }
+    }
+
+    #[test]
+    fn textbook_fp() {
+        let mut words = [0; 16];
+        words[9] = 1.5f32.to_bits() as i32;
+        words[12] = 2.0f32.to_bits() as i32;
+        let mut t = Tomasulo {
+            config: MachineConfig {
+                mem_size: 64,
+                mem_words: words.to_vec(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        t.init_instruction(
+            "L.D F6,34(R2)\nL.D F2,45(R3)\nMUL.D F0,F2,F4\nSUB.D F8,F6,F2\nDIV.D F10,F0,F6\nADD.D F6,F8,F2",
+        )
+        .unwrap();
+        t.step().unwrap();
+        // f6 is renamed while x6 keeps its value
+        assert_eq!(t.rg.get_freg(RegIndex(6)).state, Some(0));
+        assert_eq!(t.rg.get_reg(RegIndex(6)).state, None);
+        t.run_to_completion(200).unwrap();
+        let fregs = [0, 2, 6, 8, 10].map(|i| t.rg.get_float(RegIndex(i)));
+        assert_eq!(fregs, [8.0, 2.0, 1.5, -0.5, 8.0 / 1.5]);
+        assert_eq!(t.rg.get_reg(RegIndex(6)).value, 6);
+        let timing = t.history.iter().map(|v| v.timing).collect::<Vec<_>>();
+        // the divide waits for the multiply, which waits for the second load
+        assert!(timing[2].exec_start > timing[1].write_result);
+        assert!(timing[4].exec_start > timing[2].write_result);
+        assert_eq!(
+            timing[2].exec_complete.unwrap() - timing[2].exec_start.unwrap() + 1,
+            t.config.latency.fmul as u32
+        );
+    }
+
+    #[test]
+    fn status_table() {
+        let mut t = Tomasulo::default();
+        t.init_instruction("mul x1 x2 x3\nadd x4 x1 x2").unwrap();
+        t.run_to(20).unwrap();
+        let status = t.instr_status();
+        assert_eq!(t.instr_count(), status.len());
+        let timing = status.iter().map(|v| v.timing).collect::<Vec<_>>();
+        // mul: issue 1, executes 10 cycles from 2, writes back at 12
+        assert_eq!(timing[0].issue, 1);
+        assert_eq!(timing[0].exec_start, Some(2));
+        assert_eq!(timing[0].exec_complete, Some(11));
+        assert_eq!(timing[0].write_result, Some(12));
+        assert_eq!(timing[0].commit, Some(13));
+        // add waits for x1 and starts the cycle after the broadcast
+        assert_eq!(timing[1].issue, 2);
+        assert_eq!(timing[1].exec_start, Some(13));
+        assert_eq!(timing[1].exec_complete, Some(14));
+        assert_eq!(timing[1].write_result, Some(15));
+        assert_eq!(timing[1].commit, Some(16));
+        // the add waits in its station on the mul
+        assert_eq!(status[0].station, Some((RsType::Mul, 0)));
+        assert_eq!(status[1].station, Some((RsType::Add, 0)));
+        assert_eq!(status[1].sources, [Some(status[0].tag), None]);
     }
 }
@@ -1,17 +1,13 @@
-use anyhow::{anyhow, Result};
-use core::panic;
 use serde::{Deserialize, Serialize};
-use std::{fmt::Display, sync::RwLock};
+use std::fmt::Display;
 
-use lazy_static::lazy_static;
+use super::predictor::BranchPredictor;
+use super::reg::RegIndex;
+use super::rename::Renamer;
+use super::rob::{Rob, RobTag};
+use super::rs::{Rs, StallReason};
 
-use super::rs::RS;
-
-lazy_static! {
-    pub static ref PC: Box<RwLock<Pc>> = Box::new(RwLock::new(Pc::default()));
-}
-
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct Pc {
     pub index: u32,
     pub instrutions: Vec<Instrution>,
@@ -29,185 +25,300 @@ impl Pc {
         self.instrutions = instrutions;
         self.index = 0;
     }
-    pub fn run(&mut self) -> Result<()> {
-        let instr = self
-            .instrutions
-            .get(self.index as usize)
-            .ok_or(anyhow!("No rest instruction"))?;
-        let mut rs = RS.write().unwrap();
-        rs.try_issue(instr.to_owned())?;
-        self.index += 1;
-        Ok(())
+    /// Whether fetching has run past the last instruction.
+    pub fn is_done(&self) -> bool {
+        self.index as usize >= self.instrutions.len()
+    }
+    /// Issue the instruction at `index` and move to the predicted next one.
+    /// Returns `None` once every instruction has been fetched, and the stall
+    /// reason when the instruction has to wait, keeping it at `index`.
+    pub fn run(
+        &mut self,
+        rs: &mut Rs,
+        rg: &mut dyn Renamer,
+        rob: &mut Rob,
+        predictor: &dyn BranchPredictor,
+        cycle: u32,
+    ) -> Result<Option<RobTag>, StallReason> {
+        let Some(instr) = self.instrutions.get(self.index as usize) else {
+            return Ok(None);
+        };
+        let next = match *instr {
+            Instrution::Jal(_, target) => target,
+            Instrution::Beq(_, _, target)
+            | Instrution::Bne(_, _, target)
+            | Instrution::Blt(_, _, target)
+            | Instrution::Bge(_, _, target)
+                if predictor.predict(self.index) =>
+            {
+                target
+            }
+            _ => self.index + 1,
+        };
+        let tag = rs.try_issue(instr.to_owned(), rg, rob, self.index, next, cycle)?;
+        self.index = next;
+        Ok(Some(tag))
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
 pub enum Instrution {
-    Lw(i8, i8, i8),
-    Sw(i8, i8, i8),
-    Add(i8, i8, i8),
-    Sub(i8, i8, i8),
-    Mul(i8, i8, i8),
-    Div(i8, i8, i8),
+    /// `lw rd imm rs1`, loads the word at rs1 + imm.
+    Lw(RegIndex, i32, RegIndex),
+    /// `sw rs2 imm rs1`, stores rs2 at rs1 + imm.
+    Sw(RegIndex, i32, RegIndex),
+    /// Register-register operations `op rd rs1 rs2`.
+    Add(RegIndex, RegIndex, RegIndex),
+    Sub(RegIndex, RegIndex, RegIndex),
+    Sll(RegIndex, RegIndex, RegIndex),
+    Slt(RegIndex, RegIndex, RegIndex),
+    Sltu(RegIndex, RegIndex, RegIndex),
+    Xor(RegIndex, RegIndex, RegIndex),
+    Srl(RegIndex, RegIndex, RegIndex),
+    Sra(RegIndex, RegIndex, RegIndex),
+    Or(RegIndex, RegIndex, RegIndex),
+    And(RegIndex, RegIndex, RegIndex),
+    Mul(RegIndex, RegIndex, RegIndex),
+    Div(RegIndex, RegIndex, RegIndex),
+    Rem(RegIndex, RegIndex, RegIndex),
+    /// Register-immediate operations `op rd rs1 imm`.
+    Addi(RegIndex, RegIndex, i32),
+    Slti(RegIndex, RegIndex, i32),
+    Sltiu(RegIndex, RegIndex, i32),
+    Xori(RegIndex, RegIndex, i32),
+    Ori(RegIndex, RegIndex, i32),
+    Andi(RegIndex, RegIndex, i32),
+    Slli(RegIndex, RegIndex, i32),
+    Srli(RegIndex, RegIndex, i32),
+    Srai(RegIndex, RegIndex, i32),
+    /// `lui rd imm`, rd receives imm << 12.
+    Lui(RegIndex, i32),
+    /// `auipc rd imm`, rd receives the instruction index plus imm << 12.
+    Auipc(RegIndex, i32),
+    /// Conditional branches `b rs1 rs2 target`, the target is an instruction index.
+    Beq(RegIndex, RegIndex, u32),
+    Bne(RegIndex, RegIndex, u32),
+    Blt(RegIndex, RegIndex, u32),
+    Bge(RegIndex, RegIndex, u32),
+    /// `jal rd target`, rd receives the index of the next instruction.
+    Jal(RegIndex, u32),
+    /// `jalr rd imm rs1`, jumps to instruction index rs1 + imm.
+    Jalr(RegIndex, i32, RegIndex),
+    /// `flw fd imm rs1`, loads a float into an FP register.
+    Flw(RegIndex, i32, RegIndex),
+    /// `fsw fs2 imm rs1`, stores an FP register.
+    Fsw(RegIndex, i32, RegIndex),
+    /// Single-precision operations `op fd fs1 fs2` on FP registers.
+    Fadd(RegIndex, RegIndex, RegIndex),
+    Fsub(RegIndex, RegIndex, RegIndex),
+    Fmul(RegIndex, RegIndex, RegIndex),
+    Fdiv(RegIndex, RegIndex, RegIndex),
 }
 
 impl Instrution {
-    pub fn to_tuple(&self) -> (String, String, String, String) {
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            Self::Beq(..) | Self::Bne(..) | Self::Blt(..) | Self::Bge(..)
+        )
+    }
+
+    pub fn is_load(&self) -> bool {
+        matches!(self, Self::Lw(..) | Self::Flw(..))
+    }
+
+    pub fn is_store(&self) -> bool {
+        matches!(self, Self::Sw(..) | Self::Fsw(..))
+    }
+
+    /// Whether the destination is an FP register.
+    pub fn fp_dest(&self) -> bool {
+        matches!(
+            self,
+            Self::Flw(..) | Self::Fadd(..) | Self::Fsub(..) | Self::Fmul(..) | Self::Fdiv(..)
+        )
+    }
+
+    /// Whether the result is broadcast on the CDB.
+    pub fn writes_back(&self) -> bool {
+        !self.is_store() && !self.is_branch()
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
         match self {
-            Self::Lw(rd, imm, rs) => (
-                "lw".to_owned(),
-                "x".to_owned() + &rd.to_string(),
-                imm.to_string(),
-                "x".to_owned() + &rs.to_string(),
-            ),
-            Self::Sw(rd, imm, rs) => (
-                "sw".to_owned(),
-                "x".to_owned() + &rd.to_string(),
-                imm.to_string(),
-                "x".to_owned() + &rs.to_string(),
-            ),
-            Self::Add(rd, rs1, rs2) => (
-                "add".to_owned(),
-                "x".to_owned() + &rd.to_string(),
-                "x".to_owned() + &rs1.to_string(),
-                "x".to_owned() + &rs2.to_string(),
-            ),
-            Self::Sub(rd, rs1, rs2) => (
-                "sub".to_owned(),
-                "x".to_owned() + &rd.to_string(),
-                "x".to_owned() + &rs1.to_string(),
-                "x".to_owned() + &rs2.to_string(),
-            ),
-            Self::Mul(rd, rs1, rs2) => (
-                "mul".to_owned(),
-                "x".to_owned() + &rd.to_string(),
-                "x".to_owned() + &rs1.to_string(),
-                "x".to_owned() + &rs2.to_string(),
-            ),
-            Self::Div(rd, rs1, rs2) => (
-                "div".to_owned(),
-                "x".to_owned() + &rd.to_string(),
-                "x".to_owned() + &rs1.to_string(),
-                "x".to_owned() + &rs2.to_string(),
-            ),
+            Self::Lw(..) => "lw",
+            Self::Sw(..) => "sw",
+            Self::Add(..) => "add",
+            Self::Sub(..) => "sub",
+            Self::Sll(..) => "sll",
+            Self::Slt(..) => "slt",
+            Self::Sltu(..) => "sltu",
+            Self::Xor(..) => "xor",
+            Self::Srl(..) => "srl",
+            Self::Sra(..) => "sra",
+            Self::Or(..) => "or",
+            Self::And(..) => "and",
+            Self::Mul(..) => "mul",
+            Self::Div(..) => "div",
+            Self::Rem(..) => "rem",
+            Self::Addi(..) => "addi",
+            Self::Slti(..) => "slti",
+            Self::Sltiu(..) => "sltiu",
+            Self::Xori(..) => "xori",
+            Self::Ori(..) => "ori",
+            Self::Andi(..) => "andi",
+            Self::Slli(..) => "slli",
+            Self::Srli(..) => "srli",
+            Self::Srai(..) => "srai",
+            Self::Lui(..) => "lui",
+            Self::Auipc(..) => "auipc",
+            Self::Beq(..) => "beq",
+            Self::Bne(..) => "bne",
+            Self::Blt(..) => "blt",
+            Self::Bge(..) => "bge",
+            Self::Jal(..) => "jal",
+            Self::Jalr(..) => "jalr",
+            Self::Flw(..) => "flw",
+            Self::Fsw(..) => "fsw",
+            Self::Fadd(..) => "fadd.s",
+            Self::Fsub(..) => "fsub.s",
+            Self::Fmul(..) => "fmul.s",
+            Self::Fdiv(..) => "fdiv.s",
         }
     }
-}
-impl From<&str> for Instrution {
-    fn from(value: &str) -> Self {
-        let parts: Vec<&str> = value.split(' ').collect();
-        if parts.len() != 4 {
-            println!("{}", value);
-            panic!("Parse Error")
+
+    /// Destination register, if any, in the FP file when [`Self::fp_dest`].
+    pub fn dest(&self) -> Option<RegIndex> {
+        match *self {
+            Self::Lw(rd, _, _)
+            | Self::Add(rd, _, _)
+            | Self::Sub(rd, _, _)
+            | Self::Sll(rd, _, _)
+            | Self::Slt(rd, _, _)
+            | Self::Sltu(rd, _, _)
+            | Self::Xor(rd, _, _)
+            | Self::Srl(rd, _, _)
+            | Self::Sra(rd, _, _)
+            | Self::Or(rd, _, _)
+            | Self::And(rd, _, _)
+            | Self::Mul(rd, _, _)
+            | Self::Div(rd, _, _)
+            | Self::Rem(rd, _, _)
+            | Self::Addi(rd, _, _)
+            | Self::Slti(rd, _, _)
+            | Self::Sltiu(rd, _, _)
+            | Self::Xori(rd, _, _)
+            | Self::Ori(rd, _, _)
+            | Self::Andi(rd, _, _)
+            | Self::Slli(rd, _, _)
+            | Self::Srli(rd, _, _)
+            | Self::Srai(rd, _, _)
+            | Self::Lui(rd, _)
+            | Self::Auipc(rd, _)
+            | Self::Jal(rd, _)
+            | Self::Jalr(rd, _, _)
+            | Self::Flw(rd, _, _)
+            | Self::Fadd(rd, _, _)
+            | Self::Fsub(rd, _, _)
+            | Self::Fmul(rd, _, _)
+            | Self::Fdiv(rd, _, _) => Some(rd),
+            Self::Sw(..)
+            | Self::Fsw(..)
+            | Self::Beq(..)
+            | Self::Bne(..)
+            | Self::Blt(..)
+            | Self::Bge(..) => None,
         }
-        match parts[0] {
-            "lw" => {
-                let r1 = parts[1]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let imm = parts[2].parse::<i8>().expect("Check your instruction");
-                let r2 = parts[3]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                Instrution::Lw(r1, imm, r2)
-            }
-            "sw" => {
-                let r1 = parts[1]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let imm = parts[2].parse::<i8>().expect("Check your instruction");
-                let r2 = parts[3]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                Instrution::Sw(r1, imm, r2)
-            }
-            "add" => {
-                let r1 = parts[1]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r2 = parts[2]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r3 = parts[3]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                Instrution::Add(r1, r2, r3)
-            }
-            "sub" => {
-                let r1 = parts[1]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r2 = parts[2]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r3 = parts[3]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                Instrution::Sub(r1, r2, r3)
-            }
-            "mul" => {
-                let r1 = parts[1]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r2 = parts[2]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r3 = parts[3]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                Instrution::Mul(r1, r2, r3)
-            }
-            "div" => {
-                let r1 = parts[1]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r2 = parts[2]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                let r3 = parts[3]
-                    .split_at(1)
-                    .1
-                    .parse::<i8>()
-                    .expect("Check your instruction");
-                Instrution::Div(r1, r2, r3)
-            }
-            _ => {
-                todo!()
-            }
+    }
+
+    /// Source registers read at issue. The FP operands of FP arithmetic and
+    /// the value of `fsw` are FP registers.
+    pub fn sources(&self) -> Vec<RegIndex> {
+        match *self {
+            Self::Lw(_, _, rs)
+            | Self::Flw(_, _, rs)
+            | Self::Jalr(_, _, rs)
+            | Self::Addi(_, rs, _)
+            | Self::Slti(_, rs, _)
+            | Self::Sltiu(_, rs, _)
+            | Self::Xori(_, rs, _)
+            | Self::Ori(_, rs, _)
+            | Self::Andi(_, rs, _)
+            | Self::Slli(_, rs, _)
+            | Self::Srli(_, rs, _)
+            | Self::Srai(_, rs, _) => vec![rs],
+            Self::Sw(rs1, _, rs2)
+            | Self::Fsw(rs1, _, rs2)
+            | Self::Fadd(_, rs1, rs2)
+            | Self::Fsub(_, rs1, rs2)
+            | Self::Fmul(_, rs1, rs2)
+            | Self::Fdiv(_, rs1, rs2)
+            | Self::Add(_, rs1, rs2)
+            | Self::Sub(_, rs1, rs2)
+            | Self::Sll(_, rs1, rs2)
+            | Self::Slt(_, rs1, rs2)
+            | Self::Sltu(_, rs1, rs2)
+            | Self::Xor(_, rs1, rs2)
+            | Self::Srl(_, rs1, rs2)
+            | Self::Sra(_, rs1, rs2)
+            | Self::Or(_, rs1, rs2)
+            | Self::And(_, rs1, rs2)
+            | Self::Mul(_, rs1, rs2)
+            | Self::Div(_, rs1, rs2)
+            | Self::Rem(_, rs1, rs2)
+            | Self::Beq(rs1, rs2, _)
+            | Self::Bne(rs1, rs2, _)
+            | Self::Blt(rs1, rs2, _)
+            | Self::Bge(rs1, rs2, _) => vec![rs1, rs2],
+            Self::Lui(..) | Self::Auipc(..) | Self::Jal(..) => vec![],
         }
     }
-}
 
+    /// Mnemonic and operands in assembler order.
+    pub fn to_tuple(&self) -> (String, String, String, String) {
+        let x = |r: &RegIndex| "x".to_owned() + &r.to_string();
+        let f = |r: &RegIndex| "f".to_owned() + &r.to_string();
+        let (a, b, c) = match self {
+            Self::Lw(rd, imm, rs) | Self::Sw(rd, imm, rs) | Self::Jalr(rd, imm, rs) => {
+                (x(rd), imm.to_string(), x(rs))
+            }
+            Self::Add(rd, rs1, rs2)
+            | Self::Sub(rd, rs1, rs2)
+            | Self::Sll(rd, rs1, rs2)
+            | Self::Slt(rd, rs1, rs2)
+            | Self::Sltu(rd, rs1, rs2)
+            | Self::Xor(rd, rs1, rs2)
+            | Self::Srl(rd, rs1, rs2)
+            | Self::Sra(rd, rs1, rs2)
+            | Self::Or(rd, rs1, rs2)
+            | Self::And(rd, rs1, rs2)
+            | Self::Mul(rd, rs1, rs2)
+            | Self::Div(rd, rs1, rs2)
+            | Self::Rem(rd, rs1, rs2) => (x(rd), x(rs1), x(rs2)),
+            Self::Addi(rd, rs1, imm)
+            | Self::Slti(rd, rs1, imm)
+            | Self::Sltiu(rd, rs1, imm)
+            | Self::Xori(rd, rs1, imm)
+            | Self::Ori(rd, rs1, imm)
+            | Self::Andi(rd, rs1, imm)
+            | Self::Slli(rd, rs1, imm)
+            | Self::Srli(rd, rs1, imm)
+            | Self::Srai(rd, rs1, imm) => (x(rd), x(rs1), imm.to_string()),
+            Self::Lui(rd, imm) | Self::Auipc(rd, imm) => (x(rd), imm.to_string(), "".to_owned()),
+            Self::Beq(rs1, rs2, target)
+            | Self::Bne(rs1, rs2, target)
+            | Self::Blt(rs1, rs2, target)
+            | Self::Bge(rs1, rs2, target) => (x(rs1), x(rs2), target.to_string()),
+            Self::Jal(rd, target) => (x(rd), target.to_string(), "".to_owned()),
+            Self::Flw(rd, imm, rs) | Self::Fsw(rd, imm, rs) => (f(rd), imm.to_string(), x(rs)),
+            Self::Fadd(rd, rs1, rs2)
+            | Self::Fsub(rd, rs1, rs2)
+            | Self::Fmul(rd, rs1, rs2)
+            | Self::Fdiv(rd, rs1, rs2) => (f(rd), f(rs1), f(rs2)),
+        };
+        (self.mnemonic().to_owned(), a, b, c)
+    }
+}
 impl Display for Instrution {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -216,10 +327,7 @@ impl Display for Instrution {
             match self {
                 Self::Lw(_, _, _) => "load",
                 Self::Sw(_, _, _) => "store",
-                Self::Add(_, _, _) => "add",
-                Self::Mul(_, _, _) => "mul",
-                Self::Sub(_, _, _) => "sub",
-                Self::Div(_, _, _) => "div",
+                v => v.mnemonic(),
             }
         )
     }
@@ -232,7 +340,7 @@ mod test {
     #[test]
     fn string_to_instruction() {
         let str = "lw x15 -20 x8";
-        let instr: Instrution = str.into();
+        let instr: Instrution = str.parse().unwrap();
         let str = serde_json::to_string(&instr).unwrap();
         assert_eq!(str, r#"{"Lw":[15,-20,8]}"#);
     }
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+pub const DEFAULT_PREDICTOR_ENTRIES: usize = 16;
+
+/// Which direction predictor a machine uses.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Prediction {
+    NotTaken,
+    Taken,
+    #[default]
+    TwoBit,
+}
+
+impl Prediction {
+    /// A fresh predictor, `entries` being the size of its table if it has
+    /// one.
+    pub fn build(self, entries: usize) -> Box<dyn BranchPredictor> {
+        match self {
+            Prediction::NotTaken => Box::new(NotTaken),
+            Prediction::Taken => Box::new(Taken),
+            Prediction::TwoBit => Box::new(TwoBit::new(entries)),
+        }
+    }
+}
+
+/// Direction predictor consulted when a conditional branch is issued and
+/// trained when it commits. `pc` is the index of the branch instruction.
+pub trait BranchPredictor: Debug {
+    fn name(&self) -> &'static str;
+    fn predict(&self, pc: u32) -> bool;
+    fn update(&mut self, pc: u32, taken: bool);
+    fn box_clone(&self) -> Box<dyn BranchPredictor>;
+}
+
+impl Clone for Box<dyn BranchPredictor> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+impl Default for Box<dyn BranchPredictor> {
+    fn default() -> Self {
+        Box::<TwoBit>::default()
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct NotTaken;
+
+impl BranchPredictor for NotTaken {
+    fn name(&self) -> &'static str {
+        "not taken"
+    }
+    fn predict(&self, _pc: u32) -> bool {
+        false
+    }
+    fn update(&mut self, _pc: u32, _taken: bool) {}
+    fn box_clone(&self) -> Box<dyn BranchPredictor> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct Taken;
+
+impl BranchPredictor for Taken {
+    fn name(&self) -> &'static str {
+        "taken"
+    }
+    fn predict(&self, _pc: u32) -> bool {
+        true
+    }
+    fn update(&mut self, _pc: u32, _taken: bool) {}
+    fn box_clone(&self) -> Box<dyn BranchPredictor> {
+        Box::new(self.clone())
+    }
+}
+
+/// Table of two-bit saturating counters indexed by the branch address.
+/// Counters 0 and 1 predict not taken, 2 and 3 predict taken.
+#[derive(Clone, Debug)]
+pub struct TwoBit {
+    pub table: Vec<u8>,
+}
+
+impl Default for TwoBit {
+    fn default() -> Self {
+        Self::new(DEFAULT_PREDICTOR_ENTRIES)
+    }
+}
+
+impl TwoBit {
+    pub fn new(size: usize) -> Self {
+        Self {
+            table: vec![1; size.max(1)],
+        }
+    }
+    fn counter(&self, pc: u32) -> usize {
+        pc as usize % self.table.len()
+    }
+}
+
+impl BranchPredictor for TwoBit {
+    fn name(&self) -> &'static str {
+        "two-bit"
+    }
+    fn predict(&self, pc: u32) -> bool {
+        self.table[self.counter(pc)] >= 2
+    }
+    fn update(&mut self, pc: u32, taken: bool) {
+        let i = self.counter(pc);
+        self.table[i] = if taken {
+            (self.table[i] + 1).min(3)
+        } else {
+            self.table[i].saturating_sub(1)
+        };
+    }
+    fn box_clone(&self) -> Box<dyn BranchPredictor> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BranchPredictor, TwoBit};
+
+    #[test]
+    fn two_bit_hysteresis() {
+        let mut bp = TwoBit::new(4);
+        assert!(!bp.predict(1));
+        bp.update(1, true);
+        assert!(bp.predict(1));
+        bp.update(1, true);
+        bp.update(1, false);
+        assert!(bp.predict(1));
+        assert!(!bp.predict(2));
+    }
+}
@@ -1,60 +1,127 @@
-use super::rs::RsType;
-use lazy_static::lazy_static;
-use std::{fmt::Display, sync::RwLock};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
-pub type RegState = Option<(RsType, u8)>;
+use super::rob::RobTag;
 
-lazy_static! {
-    pub static ref REG_GROUP: RwLock<RegGroup> = RwLock::new(RegGroup::default());
+/// The ROB entry that will produce the register's next value.
+pub type RegState = Option<RobTag>;
+
+/// Index of a register, checked against the size of the register file when
+/// the program is assembled.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(transparent)]
+pub struct RegIndex(pub u8);
+
+impl RegIndex {
+    /// `index` if a file of `count` registers has it.
+    pub fn new(index: usize, count: usize) -> Option<Self> {
+        u8::try_from(index)
+            .ok()
+            .filter(|_| index < count)
+            .map(RegIndex)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Display for RegIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
 pub struct Reg {
     pub state: RegState,
     pub value: i32,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RegGroup {
-    pub regs: [Reg; 32],
+    pub regs: Vec<Reg>,
+    /// Floating-point registers with their own status, each value is the
+    /// bit pattern of an `f32`.
+    pub fregs: Vec<Reg>,
 }
 
 impl Default for RegGroup {
     fn default() -> Self {
-        let mut regs = [Reg::default(); 32];
-        regs.iter_mut().enumerate().for_each(|(i, v)| {
-            v.value = i as i32;
-        });
-        Self { regs }
+        Self::new(32)
     }
 }
 impl Display for RegGroup {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.regs
             .iter()
+            .chain(self.fregs.iter())
             .try_for_each(|v| write!(f, "{:?} ", v.state))
     }
 }
 
 impl RegGroup {
+    /// `count` registers in each file, each xi starting out holding i and
+    /// each fi holding i as a float.
+    pub fn new(count: usize) -> Self {
+        let mut regs = vec![Reg::default(); count];
+        regs.iter_mut().enumerate().for_each(|(i, v)| {
+            v.value = i as i32;
+        });
+        let mut fregs = vec![Reg::default(); count];
+        fregs.iter_mut().enumerate().for_each(|(i, v)| {
+            v.value = (i as f32).to_bits() as i32;
+        });
+        Self { regs, fregs }
+    }
+
     pub fn reset(&mut self) {
-        *self = Self::default();
+        *self = Self::new(self.regs.len());
     }
 
-    pub fn get_reg(&self, index: u8) -> &Reg {
-        self.regs.get(index as usize).unwrap()
+    pub fn get_reg(&self, index: RegIndex) -> &Reg {
+        self.regs.get(index.index()).unwrap()
     }
-    pub fn set_state(&mut self, index: u8, state: RegState) {
-        self.regs.get_mut(index as usize).unwrap().state = state;
+    pub fn get_freg(&self, index: RegIndex) -> &Reg {
+        self.fregs.get(index.index()).unwrap()
     }
-    pub fn refresh_reg_state(&mut self, state: RegState, value: i32) {
-        println!("{}\n{:?}", self, state);
+    /// Value of an FP register as a float.
+    pub fn get_float(&self, index: RegIndex) -> f32 {
+        f32::from_bits(self.get_freg(index).value as u32)
+    }
+    /// x0 is hardwired to zero, so it is never renamed or written.
+    pub fn set_state(&mut self, index: RegIndex, state: RegState) {
+        if index.0 != 0 {
+            self.regs.get_mut(index.index()).unwrap().state = state;
+        }
+    }
+    /// Unlike x0, f0 is an ordinary register.
+    pub fn set_fstate(&mut self, index: RegIndex, state: RegState) {
+        self.fregs.get_mut(index.index()).unwrap().state = state;
+    }
+    /// Forget every pending producer, used when the ROB is flushed.
+    pub fn clear_states(&mut self) {
         self.regs
             .iter_mut()
-            .filter(|v| v.state == state)
-            .for_each(|v| {
-                v.state = None;
-                v.value = value;
-            });
-        println!("{}\n", self);
+            .chain(self.fregs.iter_mut())
+            .for_each(|v| v.state = None);
+    }
+    /// Write a committed value. The status is only cleared when no younger
+    /// instruction has renamed the register since.
+    pub fn commit(&mut self, index: RegIndex, tag: RobTag, value: i32) {
+        if index.0 == 0 {
+            return;
+        }
+        let reg = self.regs.get_mut(index.index()).unwrap();
+        reg.value = value;
+        if reg.state == Some(tag) {
+            reg.state = None;
+        }
+    }
+    pub fn commit_f(&mut self, index: RegIndex, tag: RobTag, value: i32) {
+        let reg = self.fregs.get_mut(index.index()).unwrap();
+        reg.value = value;
+        if reg.state == Some(tag) {
+            reg.state = None;
+        }
     }
 }
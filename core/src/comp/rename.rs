@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use super::reg::{Reg, RegGroup, RegIndex, RegState};
+use super::rob::{Rob, RobTag};
+
+/// How destination registers are renamed at issue.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Renaming {
+    /// Classic Tomasulo: the register status table names the ROB entry that
+    /// will produce each register, and the ROB holds the value until commit.
+    #[default]
+    Rob,
+    /// MIPS R10K style: a register alias table maps every architectural
+    /// register to one of a unified file of physical registers, and each
+    /// destination takes a fresh one off a free list.
+    PhysicalRegisters,
+}
+
+/// The register state seen by issue.
+pub trait Renamer {
+    /// The value of a source register, or the tag of the ROB entry that will
+    /// produce it.
+    fn read(&self, reg: RegIndex, fp: bool, rob: &Rob) -> (Option<i32>, RegState);
+    /// Whether `reg` can be given a new name right now.
+    fn can_rename(&self, reg: RegIndex, fp: bool) -> bool;
+    /// Make the ROB entry `tag` the producer of `reg`.
+    fn rename(&mut self, reg: RegIndex, fp: bool, tag: RobTag);
+    /// The ROB entry `reg` is waiting on, if any.
+    fn producer(&self, reg: RegIndex, fp: bool) -> RegState;
+}
+
+impl Renamer for RegGroup {
+    fn read(&self, reg: RegIndex, fp: bool, rob: &Rob) -> (Option<i32>, RegState) {
+        let reg = match fp {
+            true => self.get_freg(reg),
+            false => self.get_reg(reg),
+        };
+        match reg.state {
+            None => (Some(reg.value), None),
+            Some(tag) if rob.get(tag).ready() => (rob.get(tag).value, None),
+            state => (None, state),
+        }
+    }
+
+    fn can_rename(&self, _: RegIndex, _: bool) -> bool {
+        true
+    }
+
+    fn rename(&mut self, reg: RegIndex, fp: bool, tag: RobTag) {
+        match fp {
+            true => self.set_fstate(reg, Some(tag)),
+            false => self.set_state(reg, Some(tag)),
+        }
+    }
+
+    fn producer(&self, reg: RegIndex, fp: bool) -> RegState {
+        match fp {
+            true => self.get_freg(reg).state,
+            false => self.get_reg(reg).state,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct PhysReg {
+    pub value: i32,
+    /// The value has been written.
+    pub ready: bool,
+    /// ROB entry writing the register, until it commits. Results on the CDB
+    /// carry this tag.
+    pub producer: RegState,
+}
+
+/// A unified physical register file with its register alias tables. The
+/// integer registers are architectural registers `0..count` and the FP ones
+/// `count..2 * count`.
+#[derive(Default, Clone, Debug)]
+pub struct PhysRegFile {
+    pub regs: Vec<PhysReg>,
+    /// Speculative map from architectural to physical register, updated at
+    /// issue.
+    pub rat: Vec<usize>,
+    /// Committed map, updated at commit and restored on a flush.
+    pub retirement_rat: Vec<usize>,
+    /// Physical registers not mapped by either table, oldest freed first.
+    pub free: VecDeque<usize>,
+}
+
+impl PhysRegFile {
+    /// `size` physical registers holding the values of `rg`, which must have
+    /// fewer registers in both files together.
+    pub fn new(size: usize, rg: &RegGroup) -> Self {
+        let mut regs = vec![PhysReg::default(); size];
+        rg.regs
+            .iter()
+            .chain(rg.fregs.iter())
+            .zip(regs.iter_mut())
+            .for_each(|(arch, phys)| {
+                phys.value = arch.value;
+                phys.ready = true;
+            });
+        let mapped = rg.regs.len() * 2;
+        Self {
+            regs,
+            rat: (0..mapped).collect(),
+            retirement_rat: (0..mapped).collect(),
+            free: (mapped..size).collect(),
+        }
+    }
+
+    /// Architectural register number of `reg`.
+    pub fn arch(&self, reg: RegIndex, fp: bool) -> usize {
+        match fp {
+            true => self.rat.len() / 2 + reg.index(),
+            false => reg.index(),
+        }
+    }
+
+    /// Physical register `reg` is currently mapped to.
+    pub fn get(&self, reg: RegIndex, fp: bool) -> &PhysReg {
+        &self.regs[self.rat[self.arch(reg, fp)]]
+    }
+
+    /// Store a result broadcast on the CDB.
+    pub fn write(&mut self, tag: RobTag, value: i32) {
+        if let Some(reg) = self.regs.iter_mut().find(|v| v.producer == Some(tag)) {
+            reg.value = value;
+            reg.ready = true;
+        }
+    }
+
+    /// The producer `tag` of `reg` committed: its physical register becomes
+    /// the architectural one and the previous mapping is freed.
+    pub fn commit(&mut self, reg: RegIndex, fp: bool, tag: RobTag) {
+        let Some(phys) = self.regs.iter().position(|v| v.producer == Some(tag)) else {
+            return;
+        };
+        self.regs[phys].producer = None;
+        let arch = self.arch(reg, fp);
+        let old = std::mem::replace(&mut self.retirement_rat[arch], phys);
+        self.free.push_back(old);
+    }
+
+    /// Drop every mapping made since the last commit.
+    pub fn flush(&mut self) {
+        self.rat = self.retirement_rat.clone();
+        self.free = (0..self.regs.len())
+            .filter(|v| !self.retirement_rat.contains(v))
+            .collect();
+        self.free.iter().for_each(|&v| {
+            self.regs[v] = PhysReg::default();
+        });
+    }
+}
+
+impl Renamer for PhysRegFile {
+    fn read(&self, reg: RegIndex, fp: bool, _: &Rob) -> (Option<i32>, RegState) {
+        match self.get(reg, fp) {
+            PhysReg {
+                value, ready: true, ..
+            } => (Some(*value), None),
+            PhysReg { producer, .. } => (None, *producer),
+        }
+    }
+
+    fn can_rename(&self, reg: RegIndex, fp: bool) -> bool {
+        // x0 is never renamed
+        (!fp && reg.0 == 0) || !self.free.is_empty()
+    }
+
+    fn rename(&mut self, reg: RegIndex, fp: bool, tag: RobTag) {
+        if !fp && reg.0 == 0 {
+            return;
+        }
+        let phys = self.free.pop_front().unwrap();
+        self.regs[phys] = PhysReg {
+            value: 0,
+            ready: false,
+            producer: Some(tag),
+        };
+        let arch = self.arch(reg, fp);
+        self.rat[arch] = phys;
+    }
+
+    fn producer(&self, reg: RegIndex, fp: bool) -> RegState {
+        Some(self.get(reg, fp))
+            .filter(|v| !v.ready)
+            .and_then(|v| v.producer)
+    }
+}
+
+impl From<&PhysReg> for Reg {
+    fn from(value: &PhysReg) -> Self {
+        Self {
+            state: value.producer.filter(|_| !value.ready),
+            value: value.value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PhysRegFile, Renamer};
+    use crate::comp::reg::{RegGroup, RegIndex};
+    use crate::comp::rob::Rob;
+
+    #[test]
+    fn free_list() {
+        let rg = RegGroup::new(2);
+        let rob = Rob::new(4);
+        let mut prf = PhysRegFile::new(6, &rg);
+        let x1 = RegIndex(1);
+        assert_eq!(prf.read(x1, false, &rob), (Some(1), None));
+        prf.rename(x1, false, 0);
+        prf.rename(x1, false, 1);
+        assert!(!prf.can_rename(x1, false));
+        assert!(prf.can_rename(RegIndex(0), false));
+        assert_eq!(prf.read(x1, false, &rob), (None, Some(1)));
+
+        prf.write(1, 9);
+        assert_eq!(prf.read(x1, false, &rob), (Some(9), None));
+        // the first producer commits and frees the initial mapping
+        prf.commit(x1, false, 0);
+        assert_eq!(prf.free, [1]);
+        assert_eq!(prf.retirement_rat[1], 4);
+        // a flush goes back to it, dropping the second producer's register
+        prf.flush();
+        assert_eq!(prf.rat, prf.retirement_rat);
+        assert_eq!(prf.free, [1, 5]);
+    }
+}
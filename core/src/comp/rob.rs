@@ -0,0 +1,448 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt::Display;
+
+use super::mem::Mem;
+use super::pc::Instrution;
+use super::reg::{RegGroup, RegIndex, RegState};
+use super::rs::{RsType, StallReason};
+
+pub const DEFAULT_ROB_SIZE: usize = 8;
+
+/// Index of an entry in the reorder buffer. This is the tag that register
+/// status entries and reservation station operands wait on, and that is
+/// broadcast on the CDB.
+pub type RobTag = usize;
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum RobState {
+    #[default]
+    Issue,
+    Execute,
+    WriteResult,
+}
+
+impl Display for RobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                RobState::Issue => "issue",
+                RobState::Execute => "execute",
+                RobState::WriteResult => "write result",
+            }
+        )
+    }
+}
+
+/// Cycles at which an instruction went through each stage.
+#[derive(Serialize, Default, Clone, Copy, PartialEq, Debug)]
+pub struct Timing {
+    pub issue: u32,
+    pub exec_start: Option<u32>,
+    pub exec_complete: Option<u32>,
+    pub write_result: Option<u32>,
+    pub commit: Option<u32>,
+    /// Flushed from the ROB instead of committing.
+    pub squash: Option<u32>,
+}
+
+/// What an instruction was doing in a cycle, as drawn on a timeline.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    Issue,
+    /// In its station, waiting for operands or a free unit.
+    Wait,
+    Execute,
+    /// Finished executing but lost CDB arbitration.
+    CdbWait,
+    WriteResult,
+    /// Done, waiting for the older instructions to commit.
+    Retire,
+    Commit,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Stage::Issue => "issue",
+                Stage::Wait => "waiting",
+                Stage::Execute => "execute",
+                Stage::CdbWait => "waiting for CDB",
+                Stage::WriteResult => "write result",
+                Stage::Retire => "waiting to commit",
+                Stage::Commit => "commit",
+            }
+        )
+    }
+}
+
+impl Timing {
+    /// Stage in `cycle`, `None` before issue and after leaving the ROB.
+    /// Stages not reached yet are assumed to still be in progress.
+    pub fn stage(&self, cycle: u32) -> Option<Stage> {
+        let before = |v: Option<u32>| v.is_none_or(|v| cycle < v);
+        if cycle < self.issue || self.squash.is_some_and(|v| cycle >= v) {
+            None
+        } else if cycle == self.issue {
+            Some(Stage::Issue)
+        } else if before(self.exec_start) {
+            Some(Stage::Wait)
+        } else if self.exec_complete.is_none_or(|v| cycle <= v) {
+            Some(Stage::Execute)
+        } else if before(self.write_result) {
+            Some(Stage::CdbWait)
+        } else if self.write_result == Some(cycle) {
+            Some(Stage::WriteResult)
+        } else if before(self.commit) {
+            Some(Stage::Retire)
+        } else if self.commit == Some(cycle) {
+            Some(Stage::Commit)
+        } else {
+            None
+        }
+    }
+}
+
+/// Status of one dynamic instruction, as in the textbook Tomasulo table.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct InstrStatus {
+    pub pc: u32,
+    pub instr: Instrution,
+    pub tag: RobTag,
+    /// Reservation station class and index it was issued to.
+    pub station: Option<(RsType, usize)>,
+    /// Producers of its operands that had not finished at issue.
+    pub sources: [RegState; 2],
+    pub timing: Timing,
+    /// Fetched down a mispredicted path and flushed before committing.
+    pub squashed: bool,
+}
+
+impl From<&RobEntry> for InstrStatus {
+    fn from(value: &RobEntry) -> Self {
+        Self {
+            pc: value.pc,
+            instr: value.instr.unwrap(),
+            tag: value.tag,
+            station: value.station,
+            sources: value.sources,
+            timing: value.timing,
+            squashed: false,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct RobEntry {
+    pub busy: bool,
+    pub instr: Option<Instrution>,
+    /// Its own tag, so that copies taken out of the buffer still name it.
+    pub tag: RobTag,
+    /// Reservation station class and index it was issued to.
+    pub station: Option<(RsType, usize)>,
+    /// Producers of its operands that had not finished at issue.
+    pub sources: [RegState; 2],
+    pub state: RobState,
+    /// Destination register, `None` for stores.
+    pub dest: Option<RegIndex>,
+    /// `dest` is in the FP register file.
+    pub fp: bool,
+    /// Effective address, only used by stores.
+    pub addr: Option<i32>,
+    pub value: Option<i32>,
+    /// Index of the instruction.
+    pub pc: u32,
+    /// Index of the instruction issued after this one.
+    pub pred: u32,
+    /// Resolved next index, only set by branches and jumps.
+    pub target: Option<u32>,
+    /// A load that read a stale value, re-executed when it reaches the head.
+    pub replay: bool,
+    /// Address a load could not read. Raised when the load commits, since
+    /// it is harmless on the wrong path.
+    pub fault: Option<i32>,
+    pub timing: Timing,
+}
+
+impl RobEntry {
+    pub fn ready(&self) -> bool {
+        self.state == RobState::WriteResult
+    }
+
+    /// Whether the instructions issued after this one were on the wrong path.
+    pub fn mispredicted(&self) -> bool {
+        self.target.is_some_and(|v| v != self.pred)
+    }
+}
+
+/// Circular reorder buffer. Entries are allocated at `tail` on issue and
+/// retired from `head` in program order.
+#[derive(Clone, Debug)]
+pub struct Rob {
+    pub entries: Vec<RobEntry>,
+    pub head: usize,
+    pub count: usize,
+}
+
+impl Default for Rob {
+    fn default() -> Self {
+        Self::new(DEFAULT_ROB_SIZE)
+    }
+}
+
+impl Display for Rob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.iter().try_for_each(|(tag, v)| {
+            writeln!(
+                f,
+                "\trob{}\t{}\t{:?}\t{:?}\t{:?}",
+                tag,
+                v.state,
+                v.instr.map(|v| v.to_string()),
+                v.dest,
+                v.value
+            )
+        })
+    }
+}
+
+impl Rob {
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: vec![RobEntry::default(); size.max(1)],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.entries.len());
+    }
+
+    pub fn size(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count == self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn get(&self, tag: RobTag) -> &RobEntry {
+        &self.entries[tag]
+    }
+
+    pub fn get_mut(&mut self, tag: RobTag) -> &mut RobEntry {
+        &mut self.entries[tag]
+    }
+
+    /// Busy entries from oldest to youngest.
+    pub fn iter(&self) -> impl Iterator<Item = (RobTag, &RobEntry)> {
+        (0..self.count).map(|i| {
+            let tag = (self.head + i) % self.entries.len();
+            (tag, &self.entries[tag])
+        })
+    }
+
+    /// Whether a branch or jump in flight has not computed its target yet.
+    pub fn has_unresolved_branch(&self) -> bool {
+        self.iter().any(|(_, v)| {
+            v.target.is_none()
+                && v.instr
+                    .is_some_and(|v| v.is_branch() || matches!(v, Instrution::Jalr(..)))
+        })
+    }
+
+    /// Position of `tag` counted from the head, smaller is older.
+    pub fn age(&self, tag: RobTag) -> usize {
+        (tag + self.entries.len() - self.head) % self.entries.len()
+    }
+
+    /// The oldest entry.
+    pub fn head(&self) -> Option<&RobEntry> {
+        self.iter().next().map(|(_, v)| v)
+    }
+
+    pub fn push(
+        &mut self,
+        instr: Instrution,
+        dest: Option<RegIndex>,
+        pc: u32,
+        pred: u32,
+    ) -> Result<RobTag, StallReason> {
+        if self.is_full() {
+            return Err(StallReason::RobFull);
+        }
+        let tag = (self.head + self.count) % self.entries.len();
+        self.entries[tag] = RobEntry {
+            busy: true,
+            instr: Some(instr),
+            tag,
+            dest,
+            fp: instr.fp_dest(),
+            pc,
+            pred,
+            ..Default::default()
+        };
+        self.count += 1;
+        Ok(tag)
+    }
+
+    pub fn write_result(&mut self, tag: RobTag, value: i32) {
+        let entry = &mut self.entries[tag];
+        entry.value = Some(value);
+        entry.state = RobState::WriteResult;
+    }
+
+    /// Record where a branch or jump actually goes.
+    pub fn resolve(&mut self, tag: RobTag, target: u32) {
+        let entry = &mut self.entries[tag];
+        entry.target = Some(target);
+        entry.state = RobState::WriteResult;
+    }
+
+    /// Drop every entry, used after a mispredicted branch commits. Returns
+    /// the dropped entries from oldest to youngest.
+    pub fn flush(&mut self) -> Vec<RobEntry> {
+        let flushed = self.iter().map(|(_, v)| *v).collect();
+        self.entries
+            .iter_mut()
+            .for_each(|v| *v = RobEntry::default());
+        self.count = 0;
+        flushed
+    }
+
+    /// The error retiring the head entry would raise, checked before a cycle
+    /// changes anything.
+    pub fn check_commit(&self, mem: &Mem) -> Result<()> {
+        let Some(entry) = self.head().filter(|v| v.ready() && !v.replay) else {
+            return Ok(());
+        };
+        if let Some(addr) = entry.fault {
+            mem.check_word(addr)?;
+        }
+        if entry.instr.is_some_and(|v| v.is_store()) {
+            mem.check_word(entry.addr.unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Retire the head entry if its result is ready, updating the
+    /// architectural registers or memory.
+    pub fn commit(&mut self, rg: &mut RegGroup, mem: &mut Mem) -> Result<Option<RobEntry>> {
+        if self.is_empty() || !self.entries[self.head].ready() {
+            return Ok(None);
+        }
+        let tag = self.head;
+        let entry = self.entries[tag];
+        if let Some(addr) = entry.fault {
+            mem.check_word(addr)?;
+        }
+        match (entry.instr, entry.dest) {
+            (Some(instr), _) if instr.is_store() => {
+                mem.store_word(entry.addr.unwrap(), entry.value.unwrap())?;
+            }
+            (_, Some(dest)) if entry.fp => rg.commit_f(dest, tag, entry.value.unwrap()),
+            (_, Some(dest)) => rg.commit(dest, tag, entry.value.unwrap()),
+            _ => {}
+        }
+        self.entries[tag] = RobEntry::default();
+        self.head = (self.head + 1) % self.entries.len();
+        self.count -= 1;
+        Ok(Some(entry))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Rob, Stage, Timing};
+    use crate::comp::mem::Mem;
+    use crate::comp::pc::Instrution;
+    use crate::comp::reg::{RegGroup, RegIndex};
+
+    #[test]
+    fn in_order_commit() {
+        let mut rob = Rob::new(2);
+        let mut rg = RegGroup::default();
+        let mut mem = Mem::default();
+        let a = rob
+            .push(
+                Instrution::Add(RegIndex(1), RegIndex(2), RegIndex(3)),
+                Some(RegIndex(1)),
+                0,
+                1,
+            )
+            .unwrap();
+        let b = rob
+            .push(
+                Instrution::Add(RegIndex(2), RegIndex(2), RegIndex(3)),
+                Some(RegIndex(2)),
+                1,
+                2,
+            )
+            .unwrap();
+        assert!(rob.is_full());
+        assert!(rob
+            .push(
+                Instrution::Add(RegIndex(3), RegIndex(2), RegIndex(3)),
+                Some(RegIndex(3)),
+                2,
+                3
+            )
+            .is_err());
+        rob.write_result(b, 7);
+        assert!(rob.commit(&mut rg, &mut mem).unwrap().is_none());
+        rob.write_result(a, 5);
+        assert!(rob.commit(&mut rg, &mut mem).unwrap().is_some());
+        assert!(rob.commit(&mut rg, &mut mem).unwrap().is_some());
+        assert!(rob.is_empty());
+        assert_eq!(rg.get_reg(RegIndex(1)).value, 5);
+        assert_eq!(rg.get_reg(RegIndex(2)).value, 7);
+    }
+
+    #[test]
+    fn stages() {
+        let timing = Timing {
+            issue: 2,
+            exec_start: Some(4),
+            exec_complete: Some(5),
+            write_result: Some(7),
+            commit: Some(9),
+            squash: None,
+        };
+        let stages = (1..=10).map(|v| timing.stage(v)).collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            vec![
+                None,
+                Some(Stage::Issue),
+                Some(Stage::Wait),
+                Some(Stage::Execute),
+                Some(Stage::Execute),
+                Some(Stage::CdbWait),
+                Some(Stage::WriteResult),
+                Some(Stage::Retire),
+                Some(Stage::Commit),
+                None,
+            ]
+        );
+        // still executing, then flushed
+        let timing = Timing {
+            exec_complete: None,
+            write_result: None,
+            commit: None,
+            squash: Some(8),
+            ..timing
+        };
+        assert_eq!(timing.stage(7), Some(Stage::Execute));
+        assert_eq!(timing.stage(8), None);
+    }
+}
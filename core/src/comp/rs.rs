@@ -1,26 +1,98 @@
-use std::{fmt::Display, sync::RwLock};
+use std::fmt::Display;
 
-use anyhow::{anyhow, Result};
-use lazy_static::lazy_static;
+use anyhow::Result;
+use serde::Serialize;
 
+use super::cdb::{Cdb, Request};
+use super::config::{ClassCount, Latency, MachineConfig};
+use super::fu::{Dispatch, FuPool};
+use super::lsq::{Access, Lsq};
+use super::mem::Mem;
 use super::pc::Instrution;
-use super::reg::{RegState, REG_GROUP};
+use super::reg::{RegIndex, RegState};
+use super::rename::Renamer;
+use super::rob::{Rob, RobState, RobTag};
+use super::trace::Event;
+use super::CycleReport;
 
-lazy_static! {
-    pub static ref RS: RwLock<Rs> = RwLock::new(Rs::default());
+#[derive(Clone)]
+pub struct Rs {
+    pub load: Vec<Slot>,
+    pub store: Vec<Slot>,
+    pub add: Vec<Slot>,
+    /// Shared by multiply, divide and remainder.
+    pub mul: Vec<Slot>,
+    pub latency: Latency,
+    pub units: ClassCount<FuPool>,
+    pub lsq: Lsq,
+    pub cdb: Cdb,
 }
 
-#[derive(Default)]
-pub struct Rs {
-    pub load: [Slot; 3],
-    pub store: [Slot; 3],
-    pub add: [Slot; 3],
-    pub mul: [Slot; 2],
+impl Default for Rs {
+    fn default() -> Self {
+        Self::new(&MachineConfig::default())
+    }
 }
 
 impl Rs {
+    pub fn new(config: &MachineConfig) -> Self {
+        Self {
+            load: vec![Slot::default(); config.stations.load],
+            store: vec![Slot::default(); config.stations.store],
+            add: vec![Slot::default(); config.stations.add],
+            mul: vec![Slot::default(); config.stations.mul],
+            latency: config.latency,
+            units: ClassCount {
+                load: FuPool::new(config.units.load, config.pipelined.load, config.dispatch),
+                store: FuPool::new(config.units.store, config.pipelined.store, config.dispatch),
+                add: FuPool::new(config.units.add, config.pipelined.add, config.dispatch),
+                mul: FuPool::new(config.units.mul, config.pipelined.mul, config.dispatch),
+            },
+            lsq: Lsq::new(config.lsq, config.disambiguation),
+            cdb: Cdb::new(config.cdb, config.arbitration),
+        }
+    }
+    pub fn slots(&self, class: RsType) -> &[Slot] {
+        match class {
+            RsType::Load => &self.load,
+            RsType::Store => &self.store,
+            RsType::Add => &self.add,
+            RsType::Mul => &self.mul,
+        }
+    }
+    pub fn slots_mut(&mut self, class: RsType) -> &mut [Slot] {
+        match class {
+            RsType::Load => &mut self.load,
+            RsType::Store => &mut self.store,
+            RsType::Add => &mut self.add,
+            RsType::Mul => &mut self.mul,
+        }
+    }
+    /// The stations of a class together with its functional units and the
+    /// load/store queue.
+    fn class_mut(&mut self, class: RsType) -> (&mut [Slot], &mut FuPool, &mut Lsq) {
+        let fu = self.units.get_mut(class);
+        let slots = match class {
+            RsType::Load => &mut self.load,
+            RsType::Store => &mut self.store,
+            RsType::Add => &mut self.add,
+            RsType::Mul => &mut self.mul,
+        };
+        (slots, fu, &mut self.lsq)
+    }
     pub fn reset(&mut self) {
-        *self = Self::default();
+        [
+            &mut self.load,
+            &mut self.store,
+            &mut self.add,
+            &mut self.mul,
+        ]
+        .into_iter()
+        .for_each(|v| v.iter_mut().for_each(|v| v.reset()));
+        RsType::ALL
+            .into_iter()
+            .for_each(|class| self.units.get_mut(class).reset());
+        self.lsq.reset();
     }
 }
 
@@ -37,7 +109,8 @@ impl Display for Rs {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
 pub enum RsType {
     Load,
     Store,
@@ -45,6 +118,10 @@ pub enum RsType {
     Mul,
 }
 
+impl RsType {
+    pub const ALL: [RsType; 4] = [RsType::Add, RsType::Mul, RsType::Load, RsType::Store];
+}
+
 impl Display for RsType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -61,16 +138,53 @@ impl Display for RsType {
     }
 }
 
+/// Why the instruction at the PC could not issue in a cycle. It is held and
+/// tried again the next cycle.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StallReason {
+    /// Every station of the class is busy.
+    NoStation(RsType),
+    RobFull,
+    LsqFull,
+    /// The free list of physical registers is empty.
+    NoFreeRegister,
+    /// Speculation is off and an older branch has not resolved yet.
+    BranchPending,
+}
+
+impl Display for StallReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StallReason::NoStation(class) => write!(f, "no free {} station", class),
+            StallReason::RobFull => write!(f, "ROB full"),
+            StallReason::LsqFull => write!(f, "LSQ full"),
+            StallReason::NoFreeRegister => write!(f, "no free physical register"),
+            StallReason::BranchPending => write!(f, "branch pending"),
+        }
+    }
+}
+
+impl std::error::Error for StallReason {}
+
 #[derive(Default, Clone, Copy)]
 pub struct Slot {
     pub busy: bool,
-    pub time: i8,
+    pub time: u8,
     pub addr: Option<i32>,
     pub op: Option<Instrution>,
     pub vj: Option<i32>,
     pub vk: Option<i32>,
-    pub qj: Option<(RsType, u8)>,
-    pub qk: Option<(RsType, u8)>,
+    pub qj: RegState,
+    pub qk: RegState,
+    /// ROB entry receiving the result.
+    pub dest: RegState,
+    /// Functional unit the operation is executing on.
+    pub unit: Option<usize>,
+    /// Execution is over, the slot completes from the next cycle on.
+    pub finished: bool,
+    /// Finished executing but lost CDB arbitration, holding its result.
+    pub waiting_cdb: bool,
 }
 
 // impl Default for Slot {
@@ -98,6 +212,10 @@ impl Slot {
         self.vk = None;
         self.qj = None;
         self.qk = None;
+        self.dest = None;
+        self.unit = None;
+        self.finished = false;
+        self.waiting_cdb = false;
     }
 }
 
@@ -105,318 +223,533 @@ impl Display for Slot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
-            "\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}",
+            "\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}\t{:?}{}",
             self.busy,
             self.time,
             self.addr,
-            {
-                if let Some(instr) = self.op {
-                    match instr {
-                        Instrution::Add(_, _, _) => "add",
-                        Instrution::Sub(_, _, _) => "sub",
-                        Instrution::Mul(_, _, _) => "mul",
-                        Instrution::Lw(_, _, _) => "lw",
-                        Instrution::Sw(_, _, _) => "sw",
-                        _ => "unknown",
-                    }
-                } else {
-                    ""
-                }
-            },
+            self.op.map_or("", |v| v.mnemonic()),
             self.vj,
             self.vk,
             {
                 if let Some(qj) = self.qj {
-                    format!("{}{}", qj.0, qj.1)
+                    format!("rob{}", qj)
                 } else {
                     "".to_string()
                 }
             },
             {
                 if let Some(qk) = self.qk {
-                    format!("{}{}", qk.0, qk.1)
+                    format!("rob{}", qk)
                 } else {
                     "".to_string()
                 }
-            }
+            },
+            if self.waiting_cdb { "\tcdb" } else { "" }
         )
     }
 }
 
-impl Rs {
-    pub fn try_issue(&mut self, instr: Instrution) -> Result<()> {
-        match instr {
-            Instrution::Lw(rdi, imm, rsi) => {
-                let (index, slot) = self
-                    .load
-                    .iter_mut()
-                    .enumerate()
-                    .find(|v| !v.1.busy)
-                    .ok_or(anyhow!("No Slot"))?;
-                slot.busy = true;
-                slot.time = 2;
-                slot.op = Some(instr);
-                let mut rg = REG_GROUP.write().unwrap();
-                let rs = rg.get_reg(rsi as u8);
-                if rs.state.is_none() {
-                    slot.vj = Some(rs.value);
-                } else {
-                    slot.qj = rs.state;
-                }
-                slot.addr = Some(imm as i32);
-                rg.set_state(rdi as u8, Some((RsType::Load, index as u8)));
-            }
-            Instrution::Sw(rs1i, imm, rs2i) => {
-                let slot = self
-                    .store
-                    .iter_mut()
-                    .find(|v| !v.busy)
-                    .ok_or(anyhow!("No Slot"))?;
-                slot.busy = true;
-                slot.time = 2;
-                slot.op = Some(instr);
-                let rg = REG_GROUP.read().unwrap();
-                let rs1 = rg.get_reg(rs1i as u8);
-                let rs2 = rg.get_reg(rs2i as u8);
-                if rs1.state.is_none() {
-                    slot.vj = Some(rs1.value);
-                } else {
-                    slot.qj = rs2.state;
-                }
-                if rs2.state.is_none() {
-                    slot.vk = Some(rs2.value);
-                } else {
-                    slot.qk = rs2.state;
-                }
-                slot.addr = Some(imm as i32);
-            }
-            Instrution::Add(rdi, rs1i, rs2i) => {
-                let (index, slot) = self
-                    .add
-                    .iter_mut()
-                    .enumerate()
-                    .find(|v| !v.1.busy)
-                    .ok_or(anyhow!("No Slot"))?;
-                slot.busy = true;
-                slot.time = 2;
-                slot.op = Some(instr);
-                let mut rg = REG_GROUP.write().unwrap();
-                let rs1 = rg.get_reg(rs1i as u8);
-                let rs2 = rg.get_reg(rs2i as u8);
-                if rs1.state.is_none() {
-                    slot.vj = Some(rs1.value);
-                } else {
-                    slot.qj = rs1.state;
-                }
-                if rs2.state.is_none() {
-                    slot.vk = Some(rs2.value)
-                } else {
-                    slot.qk = rs2.state
-                }
-                rg.set_state(rdi as u8, Some((RsType::Add, index as u8)));
-            }
-            Instrution::Sub(rdi, rs1i, rs2i) => {
-                let (index, slot) = self.add.iter_mut().enumerate().find(|v| !v.1.busy).unwrap();
-                slot.busy = true;
-                slot.time = 2;
-                slot.op = Some(instr);
-                let mut rg = REG_GROUP.write().unwrap();
-                let rs1 = rg.get_reg(rs1i as u8);
-                let rs2 = rg.get_reg(rs2i as u8);
-                if rs1.state.is_none() {
-                    slot.vj = Some(rs1.value);
-                } else {
-                    slot.qj = rs1.state;
-                }
-                if rs2.state.is_none() {
-                    slot.vk = Some(rs2.value)
-                } else {
-                    slot.qk = rs2.state
-                }
-                rg.set_state(rdi as u8, Some((RsType::Add, index as u8)));
-            }
-            Instrution::Mul(rdi, rs1i, rs2i) => {
-                if let Some((index, slot)) = self.mul.iter_mut().enumerate().find(|v| !v.1.busy) {
-                    slot.busy = true;
-                    slot.time = 10;
-                    slot.op = Some(instr);
-                    let mut rg = REG_GROUP.write().unwrap();
-                    let rs1 = rg.get_reg(rs1i as u8);
-                    let rs2 = rg.get_reg(rs2i as u8);
-                    if rs1.state.is_none() {
-                        slot.vj = Some(rs1.value);
-                    } else {
-                        slot.qj = rs1.state;
-                    }
-                    if rs2.state.is_none() {
-                        slot.vk = Some(rs2.value)
-                    } else {
-                        slot.qk = rs2.state
-                    }
-                    rg.set_state(rdi as u8, Some((RsType::Mul, index as u8)));
+/// Signed division following RISC-V: dividing by zero gives all ones and the
+/// overflowing `i32::MIN / -1` gives `i32::MIN`.
+pub fn div(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        -1
+    } else {
+        a.wrapping_div(b)
+    }
+}
+
+/// Signed remainder following RISC-V: the remainder of dividing by zero is
+/// the dividend.
+pub fn rem(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a
+    } else {
+        a.wrapping_rem(b)
+    }
+}
+
+/// Advance one class by a cycle. Slots that finished executing earlier
+/// complete: those that write back are returned as CDB requests, stores and
+/// branches complete right away since they broadcast nothing. Then ready
+/// slots are dispatched to free units and every executing slot counts down.
+/// A load accesses memory when it is dispatched, which needs the load/store
+/// queue to let it through. It may be on the wrong path, so an address
+/// outside memory is only recorded on its ROB entry and raised at commit.
+fn execute(
+    class: RsType,
+    slots: &mut [Slot],
+    fu: &mut FuPool,
+    lsq: &mut Lsq,
+    rob: &mut Rob,
+    mem: &Mem,
+    report: &mut CycleReport,
+) -> Vec<Request> {
+    let cycle = report.cycle;
+    let mut requests = Vec::new();
+    for (i, slot) in slots.iter_mut().enumerate().filter(|(_, v)| v.finished) {
+        let tag = slot.dest.unwrap();
+        if slot.op.unwrap().writes_back() {
+            requests.push(Request {
+                class,
+                slot: i,
+                tag,
+            });
+            continue;
+        }
+        rob.get_mut(tag).timing.write_result = Some(cycle);
+        complete(slot, rob);
+        slot.reset();
+    }
+    let mut ready = slots
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| v.busy && !v.finished && v.unit.is_none())
+        .filter(|(_, v)| v.qj.is_none() && v.qk.is_none())
+        .filter(|(_, v)| !v.op.unwrap().is_load() || lsq.access(v.dest.unwrap()) != Access::Wait)
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    if fu.dispatch == Dispatch::OldestFirst {
+        ready.sort_by_key(|&i| rob.age(slots[i].dest.unwrap()));
+    }
+    for i in ready {
+        let Some(unit) = fu.dispatch(i, cycle) else {
+            break;
+        };
+        let tag = slots[i].dest.unwrap();
+        slots[i].unit = Some(unit);
+        if slots[i].op.unwrap().is_load() {
+            let (value, source) = match lsq.access(tag) {
+                Access::Forward(store, value) => (value, Some(store)),
+                _ => {
+                    let addr = lsq.get(tag).unwrap().addr.unwrap();
+                    let value = mem.load_word(addr);
+                    rob.get_mut(tag).fault = value.is_err().then_some(addr);
+                    (value.unwrap_or(0), None)
                 }
-            }
-            #[allow(unused)]
-            Instrution::Div(r1, r2, r3) => {}
+            };
+            lsq.perform(tag, value, source);
+            // vk is unused by loads, it keeps the value read
+            slots[i].vk = Some(value);
         }
-        Ok(())
+        let entry = rob.get_mut(tag);
+        entry.state = RobState::Execute;
+        entry.timing.exec_start = Some(cycle);
+        report.started.push(tag);
+        report.events.push(Event::Dispatch { tag, class, unit });
     }
-    pub fn update(&mut self) {
-        let mut bus = false;
-        let slot = self
-            .add
-            .iter_mut()
-            .enumerate()
-            .find(|(_, v)| v.busy && v.vj.is_some() && v.vk.is_some());
-        let mut op_done = (None, 0);
-        if let Some((index, slot)) = slot {
-            if slot.time > 0 {
-                slot.time -= 1;
-            } else {
-                let value = match slot.op {
-                    Some(Instrution::Add(_, _, _)) => slot.vj.unwrap() + slot.vk.unwrap(),
-                    Some(Instrution::Sub(_, _, _)) => slot.vj.unwrap() - slot.vk.unwrap(),
-                    _ => 0,
-                };
-                op_done = (Some((RsType::Add, index as u8)), value);
-                // let value = slot.vj.unwrap() + slot.vk.unwrap();
-                slot.reset();
-            }
+    for slot in slots.iter_mut().filter(|v| v.unit.is_some()) {
+        let tag = slot.dest.unwrap();
+        slot.time = slot.time.saturating_sub(1);
+        report.events.push(Event::ExecTick {
+            tag,
+            remaining: slot.time,
+        });
+        if slot.time == 0 {
+            rob.get_mut(tag).timing.exec_complete = Some(cycle);
+            report.completed.push(tag);
+            report.events.push(Event::Complete { tag });
+            fu.release(slot.unit.take().unwrap());
+            slot.finished = true;
+        }
+    }
+    requests
+}
+
+/// The single precision float held in the bits of a register.
+pub fn f32(bits: i32) -> f32 {
+    f32::from_bits(bits as u32)
+}
+
+/// The register bits of a single precision float.
+pub fn float(value: f32) -> i32 {
+    value.to_bits() as i32
+}
+
+/// Perform the operation of a finished slot. Branches and stores only
+/// update their ROB entry, everything else returns the value to broadcast.
+fn complete(slot: &Slot, rob: &mut Rob) -> Option<i32> {
+    let tag = slot.dest.unwrap();
+    let (vj, vk) = (slot.vj.unwrap_or(0), slot.vk.unwrap_or(0));
+    let fallthrough = rob.get(tag).pc + 1;
+    let value = match slot.op.unwrap() {
+        // immediate forms hold the immediate in vk, lui and auipc hold the
+        // shifted immediate in vk and zero or the instruction index in vj
+        Instrution::Add(..)
+        | Instrution::Addi(..)
+        | Instrution::Lui(..)
+        | Instrution::Auipc(..) => vj.wrapping_add(vk),
+        Instrution::Sub(_, _, _) => vj.wrapping_sub(vk),
+        Instrution::Sll(..) | Instrution::Slli(..) => vj.wrapping_shl(vk as u32),
+        Instrution::Srl(..) | Instrution::Srli(..) => (vj as u32).wrapping_shr(vk as u32) as i32,
+        Instrution::Sra(..) | Instrution::Srai(..) => vj.wrapping_shr(vk as u32),
+        Instrution::Slt(..) | Instrution::Slti(..) => (vj < vk) as i32,
+        Instrution::Sltu(..) | Instrution::Sltiu(..) => ((vj as u32) < (vk as u32)) as i32,
+        Instrution::Xor(..) | Instrution::Xori(..) => vj ^ vk,
+        Instrution::Or(..) | Instrution::Ori(..) => vj | vk,
+        Instrution::And(..) | Instrution::Andi(..) => vj & vk,
+        Instrution::Mul(_, _, _) => vj.wrapping_mul(vk),
+        Instrution::Div(_, _, _) => div(vj, vk),
+        Instrution::Rem(_, _, _) => rem(vj, vk),
+        Instrution::Fadd(..) => float(f32(vj) + f32(vk)),
+        Instrution::Fsub(..) => float(f32(vj) - f32(vk)),
+        Instrution::Fmul(..) => float(f32(vj) * f32(vk)),
+        Instrution::Fdiv(..) => float(f32(vj) / f32(vk)),
+        Instrution::Lw(_, _, _) | Instrution::Flw(..) => vk,
+        Instrution::Sw(_, _, _) | Instrution::Fsw(..) => {
+            // memory is only written when the store commits
+            rob.get_mut(tag).addr = Some(vj.wrapping_add(slot.addr.unwrap()));
+            rob.write_result(tag, vk);
+            return None;
+        }
+        Instrution::Beq(_, _, target) => {
+            rob.resolve(tag, if vj == vk { target } else { fallthrough });
+            return None;
+        }
+        Instrution::Bne(_, _, target) => {
+            rob.resolve(tag, if vj != vk { target } else { fallthrough });
+            return None;
+        }
+        Instrution::Blt(_, _, target) => {
+            rob.resolve(tag, if vj < vk { target } else { fallthrough });
+            return None;
+        }
+        Instrution::Bge(_, _, target) => {
+            rob.resolve(tag, if vj >= vk { target } else { fallthrough });
+            return None;
         }
-        let mut rg = REG_GROUP.write().unwrap();
-        if op_done.0.is_some() && !bus {
-            bus = true;
-            rg.refresh_reg_state(op_done.0, op_done.1);
-            self.refresh(op_done.0, op_done.1);
+        Instrution::Jal(_, target) => {
+            rob.resolve(tag, target);
+            vj
         }
+        Instrution::Jalr(_, imm, _) => {
+            rob.resolve(tag, vj.wrapping_add(imm) as u32);
+            vk
+        }
+    };
+    Some(value)
+}
 
-        op_done = (None, 0);
-        let slot = self
-            .mul
-            .iter_mut()
-            .enumerate()
-            .find(|(_, v)| v.busy && v.vj.is_some() && v.vk.is_some());
-        if let Some((index, slot)) = slot {
-            if slot.time > 0 {
-                slot.time -= 1;
-            } else {
-                let value = slot.vj.unwrap() * slot.vk.unwrap();
-                op_done = (Some((RsType::Mul, index as u8)), value);
-                if !bus {
-                    slot.reset();
-                }
-            }
+/// Where an issued slot takes one of its operands from.
+#[derive(Clone, Copy)]
+enum Operand {
+    /// Unused.
+    None,
+    /// An integer or, if `true`, FP register, waiting on its producer if it
+    /// is not ready yet.
+    Reg(RegIndex, bool),
+    /// Known at issue.
+    Value(i32),
+}
+
+impl Operand {
+    fn read(self, rg: &dyn Renamer, rob: &Rob) -> (Option<i32>, RegState) {
+        match self {
+            Operand::None => (None, None),
+            Operand::Reg(reg, fp) => rg.read(reg, fp, rob),
+            Operand::Value(v) => (Some(v), None),
+        }
+    }
+}
+
+/// Station class and operands of an instruction about to issue.
+struct Issue {
+    class: RsType,
+    /// Go to `vj`/`qj` and `vk`/`qk`.
+    operands: [Operand; 2],
+    addr: Option<i32>,
+}
+
+/// Describe how `instr`, the instruction at index `pc`, issues.
+fn describe(instr: Instrution, pc: u32) -> Issue {
+    use Operand::{Reg, Value};
+    let (class, operands, addr) = match instr {
+        Instrution::Lw(_, imm, rsi) | Instrution::Flw(_, imm, rsi) => {
+            (RsType::Load, [Reg(rsi, false), Operand::None], Some(imm))
+        }
+        // vj holds the base address, vk the value to store
+        Instrution::Sw(rs1i, imm, rs2i) => (
+            RsType::Store,
+            [Reg(rs2i, false), Reg(rs1i, false)],
+            Some(imm),
+        ),
+        Instrution::Fsw(rs1i, imm, rs2i) => (
+            RsType::Store,
+            [Reg(rs2i, false), Reg(rs1i, true)],
+            Some(imm),
+        ),
+        Instrution::Add(_, rs1i, rs2i)
+        | Instrution::Sub(_, rs1i, rs2i)
+        | Instrution::Sll(_, rs1i, rs2i)
+        | Instrution::Slt(_, rs1i, rs2i)
+        | Instrution::Sltu(_, rs1i, rs2i)
+        | Instrution::Xor(_, rs1i, rs2i)
+        | Instrution::Srl(_, rs1i, rs2i)
+        | Instrution::Sra(_, rs1i, rs2i)
+        | Instrution::Or(_, rs1i, rs2i)
+        | Instrution::And(_, rs1i, rs2i)
+        | Instrution::Beq(rs1i, rs2i, _)
+        | Instrution::Bne(rs1i, rs2i, _)
+        | Instrution::Blt(rs1i, rs2i, _)
+        | Instrution::Bge(rs1i, rs2i, _) => {
+            (RsType::Add, [Reg(rs1i, false), Reg(rs2i, false)], None)
+        }
+        Instrution::Addi(_, rs1i, imm)
+        | Instrution::Slti(_, rs1i, imm)
+        | Instrution::Sltiu(_, rs1i, imm)
+        | Instrution::Xori(_, rs1i, imm)
+        | Instrution::Ori(_, rs1i, imm)
+        | Instrution::Andi(_, rs1i, imm)
+        | Instrution::Slli(_, rs1i, imm)
+        | Instrution::Srli(_, rs1i, imm)
+        | Instrution::Srai(_, rs1i, imm) => (RsType::Add, [Reg(rs1i, false), Value(imm)], None),
+        Instrution::Lui(_, imm) => (RsType::Add, [Value(0), Value(imm.wrapping_shl(12))], None),
+        Instrution::Auipc(_, imm) => (
+            RsType::Add,
+            [Value(pc as i32), Value(imm.wrapping_shl(12))],
+            None,
+        ),
+        Instrution::Mul(_, rs1i, rs2i)
+        | Instrution::Div(_, rs1i, rs2i)
+        | Instrution::Rem(_, rs1i, rs2i) => {
+            (RsType::Mul, [Reg(rs1i, false), Reg(rs2i, false)], None)
+        }
+        Instrution::Fadd(_, rs1i, rs2i) | Instrution::Fsub(_, rs1i, rs2i) => {
+            (RsType::Add, [Reg(rs1i, true), Reg(rs2i, true)], None)
         }
-        if op_done.0.is_some() && !bus {
-            bus = true;
-            rg.refresh_reg_state(op_done.0, op_done.1);
-            self.refresh(op_done.0, op_done.1);
+        Instrution::Fmul(_, rs1i, rs2i) | Instrution::Fdiv(_, rs1i, rs2i) => {
+            (RsType::Mul, [Reg(rs1i, true), Reg(rs2i, true)], None)
         }
+        // the return address is known at issue
+        Instrution::Jal(..) => (RsType::Add, [Value(pc as i32 + 1), Value(0)], None),
+        Instrution::Jalr(_, _, rsi) => (RsType::Add, [Reg(rsi, false), Value(pc as i32 + 1)], None),
+    };
+    Issue {
+        class,
+        operands,
+        addr,
+    }
+}
 
-        op_done = (None, 0);
-        let slot = self
-            .load
-            .iter_mut()
-            .enumerate()
-            .find(|(_, v)| v.busy && v.vj.is_some() && v.addr.is_some());
-        if let Some((index, slot)) = slot {
-            if slot.time > 0 {
-                slot.time -= 1;
-            } else {
-                // TODO: add mem
-                let value = 1;
-                op_done = (Some((RsType::Load, index as u8)), value);
-                if !bus {
-                    slot.reset();
-                }
-            }
+impl Rs {
+    /// Issue `instr`, the instruction at index `pc`, into a free slot and ROB
+    /// entry. `next` is the index the front end continues fetching from.
+    pub fn try_issue(
+        &mut self,
+        instr: Instrution,
+        rg: &mut dyn Renamer,
+        rob: &mut Rob,
+        pc: u32,
+        next: u32,
+        cycle: u32,
+    ) -> Result<RobTag, StallReason> {
+        if rob.is_full() {
+            return Err(StallReason::RobFull);
         }
-        if op_done.0.is_some() && !bus {
-            rg.refresh_reg_state(op_done.0, op_done.1);
-            self.refresh(op_done.0, op_done.1);
+        if instr
+            .dest()
+            .is_some_and(|v| !rg.can_rename(v, instr.fp_dest()))
+        {
+            return Err(StallReason::NoFreeRegister);
         }
-        let slot = self
-            .store
+        let Issue {
+            class,
+            operands: [j, k],
+            addr,
+        } = describe(instr, pc);
+        let time = self.latency.get(instr);
+        let (slots, _, lsq) = self.class_mut(class);
+        let slot = slots
             .iter_mut()
-            .enumerate()
-            .find(|(_, v)| v.busy && v.vj.is_some() && v.addr.is_some());
-        if let Some((_, slot)) = slot {
-            if slot.time > 0 {
-                slot.time -= 1;
-            } else {
-                slot.reset();
+            .find(|v| !v.busy)
+            .ok_or(StallReason::NoStation(class))?;
+        let memory = matches!(class, RsType::Load | RsType::Store);
+        if memory && lsq.is_full() {
+            return Err(StallReason::LsqFull);
+        }
+        let tag = rob.push(instr, instr.dest(), pc, next)?;
+        if memory {
+            lsq.push(tag, instr.is_store());
+        }
+        rob.get_mut(tag).timing.issue = cycle;
+        slot.busy = true;
+        slot.time = time;
+        slot.op = Some(instr);
+        slot.dest = Some(tag);
+        (slot.vj, slot.qj) = j.read(rg, rob);
+        (slot.vk, slot.qk) = k.read(rg, rob);
+        slot.addr = addr;
+        // the sources are read before the destination is renamed, so that
+        // an instruction reading its own destination gets the older value
+        if let Some(rdi) = instr.dest() {
+            rg.rename(rdi, instr.fp_dest(), tag);
+        }
+        let (station, sources) = RsType::ALL
+            .iter()
+            .find_map(|&class| {
+                let slots = self.slots(class);
+                let i = slots.iter().position(|v| v.busy && v.dest == Some(tag))?;
+                Some(((class, i), [slots[i].qj, slots[i].qk]))
+            })
+            .unzip();
+        let entry = rob.get_mut(tag);
+        entry.station = station;
+        entry.sources = sources.unwrap_or_default();
+        Ok(tag)
+    }
+    /// Advance every class by one cycle. Ready slots are dispatched to free
+    /// functional units and all of them execute concurrently. Finished
+    /// results compete for the CDB and are broadcast at the end of the
+    /// cycle; a result that loses waits in its slot and asks again next
+    /// cycle.
+    pub fn update(&mut self, rob: &mut Rob, mem: &Mem, report: &mut CycleReport) {
+        self.address_stage(rob);
+        let mut requests = Vec::new();
+        for class in RsType::ALL {
+            let (slots, fu, lsq) = self.class_mut(class);
+            requests.extend(execute(class, slots, fu, lsq, rob, mem, report));
+        }
+        let granted = self.cdb.arbitrate(&requests, rob);
+        for (i, request) in requests.iter().enumerate() {
+            let slot = &mut self.slots_mut(request.class)[request.slot];
+            if !granted.contains(&i) {
+                slot.waiting_cdb = true;
+                report.cdb_wait.push(request.tag);
+                continue;
             }
+            rob.get_mut(request.tag).timing.write_result = Some(report.cycle);
+            let value = complete(slot, rob).unwrap();
+            report.broadcast.push((request.tag, value));
+            report.events.push(Event::Broadcast {
+                tag: request.tag,
+                value,
+            });
+            slot.reset();
         }
+        report
+            .broadcast
+            .iter()
+            .for_each(|&(tag, value)| self.broadcast(rob, Some(tag), value));
     }
 
-    fn refresh(&mut self, state: RegState, value: i32) {
-        self.add.iter_mut().for_each(|v| {
-            if v.qj == state {
-                v.qj = None;
-                v.vj = Some(value);
-            }
-            if v.qk == state {
-                v.qk = None;
-                v.vk = Some(value);
-            }
-        });
-        self.mul.iter_mut().for_each(|v| {
-            if v.qj == state {
-                v.qj = None;
-                v.vj = Some(value);
-            }
-            if v.qk == state {
-                v.qk = None;
-                v.vk = Some(value);
+    /// Compute the effective address of every load and store whose base is
+    /// known, and record store data as soon as it is available. A store
+    /// address that exposes a load which read a stale value marks that load
+    /// for replay.
+    fn address_stage(&mut self, rob: &mut Rob) {
+        for slot in self.load.iter().chain(self.store.iter()) {
+            if !slot.busy || slot.qj.is_some() {
+                continue;
             }
-        });
-        self.load.iter_mut().for_each(|v| {
-            if v.qj == state {
-                v.qj = None;
-                v.vj = Some(value);
-            }
-            if v.qk == state {
-                v.qk = None;
-                v.vk = Some(value);
-            }
-        });
-        self.store.iter_mut().for_each(|v| {
-            if v.qj == state {
-                v.qj = None;
-                v.vj = Some(value);
+            let tag = slot.dest.unwrap();
+            let entry = *self.lsq.get(tag).unwrap();
+            if entry.addr.is_none() {
+                let addr = slot.vj.unwrap().wrapping_add(slot.addr.unwrap());
+                if let Some(load) = self.lsq.set_addr(tag, addr) {
+                    rob.get_mut(load).replay = true;
+                }
             }
-            if v.qk == state {
-                v.qk = None;
-                v.vk = Some(value);
+            if entry.store && entry.data.is_none() && slot.qk.is_none() {
+                self.lsq.set_data(tag, slot.vk.unwrap());
             }
-        });
+        }
+    }
+
+    /// Put a result on the CDB: the ROB entry and every waiting slot pick it up.
+    fn broadcast(&mut self, rob: &mut Rob, state: RegState, value: i32) {
+        rob.write_result(state.unwrap(), value);
+        self.refresh(state, value);
+    }
+
+    fn refresh(&mut self, state: RegState, value: i32) {
+        self.add
+            .iter_mut()
+            .chain(self.mul.iter_mut())
+            .chain(self.load.iter_mut())
+            .chain(self.store.iter_mut())
+            .for_each(|v| {
+                if v.qj == state {
+                    v.qj = None;
+                    v.vj = Some(value);
+                }
+                if v.qk == state {
+                    v.qk = None;
+                    v.vk = Some(value);
+                }
+            });
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::RS;
+    use super::Rs;
+    use crate::comp::fu::Dispatch;
+    use crate::comp::mem::Mem;
     use crate::comp::pc::Instrution;
+    use crate::comp::reg::{RegGroup, RegIndex};
+    use crate::comp::rob::Rob;
+    use crate::comp::CycleReport;
 
     #[test]
     fn test_issue() {
         let str = "add x15 x8 x8";
-        let instr: Instrution = str.into();
-        let mut rs = RS.write().unwrap();
-        let res = rs.try_issue(instr);
-        assert!(res.is_ok())
+        let instr: Instrution = str.parse().unwrap();
+        let mut rs = Rs::default();
+        let mut rg = RegGroup::default();
+        let mut rob = Rob::default();
+        let res = rs.try_issue(instr, &mut rg, &mut rob, 0, 1, 1);
+        assert!(res.is_ok());
+        assert_eq!(rg.get_reg(RegIndex(15)).state, Some(0))
     }
 
     #[test]
     fn test_update() {
         let str = "mul x16 x15 x8";
-        let instr: Instrution = str.into();
-        let mut rs = RS.write().unwrap();
-        let res = rs.try_issue(instr);
-        rs.update();
-        rs.update();
-        rs.update();
-        rs.update();
-        assert!(res.is_ok())
+        let instr: Instrution = str.parse().unwrap();
+        let mut rs = Rs::default();
+        let mut rg = RegGroup::default();
+        let mem = Mem::default();
+        let mut rob = Rob::default();
+        let res = rs.try_issue(instr, &mut rg, &mut rob, 0, 1, 1);
+        let mut report = CycleReport::default();
+        for _ in 0..4 {
+            rs.update(&mut rob, &mem, &mut report);
+        }
+        assert!(res.is_ok());
+        assert_eq!(report.started, vec![0]);
+    }
+
+    #[test]
+    fn dispatch_policy() {
+        let started = |dispatch: Dispatch| {
+            let mut rs = Rs::default();
+            rs.units.add.dispatch = dispatch;
+            let mut rg = RegGroup::default();
+            let mut rob = Rob::default();
+            let mem = Mem::default();
+            for (pc, instr) in ["add x1 x2 x3", "add x4 x2 x3"].iter().enumerate() {
+                let instr = instr.parse().unwrap();
+                rs.try_issue(instr, &mut rg, &mut rob, pc as u32, pc as u32 + 1, 1)
+                    .unwrap();
+            }
+            // the younger add sits in the lower slot
+            rs.add.swap(0, 1);
+            let mut report = CycleReport::default();
+            rs.update(&mut rob, &mem, &mut report);
+            report.started
+        };
+        assert_eq!(started(Dispatch::LowestIndex), vec![1]);
+        assert_eq!(started(Dispatch::OldestFirst), vec![0]);
+    }
+
+    #[test]
+    fn division_semantics() {
+        use super::{div, rem};
+        assert_eq!(div(7, -2), -3);
+        assert_eq!(rem(7, -2), 1);
+        assert_eq!(div(7, 0), -1);
+        assert_eq!(rem(7, 0), 7);
+        assert_eq!(div(i32::MIN, -1), i32::MIN);
+        assert_eq!(rem(i32::MIN, -1), 0);
     }
 }
@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::predictor::BranchPredictor;
+use super::reg::RegGroup;
+use super::rename::PhysRegFile;
+use super::rob::Rob;
+use super::rs::{Rs, StallReason};
+use super::Tomasulo;
+
+pub const DEFAULT_SNAPSHOTS: usize = 1024;
+
+/// The state before a cycle: copies of the small structures, and how far
+/// the history and the memory stores had got.
+#[derive(Clone)]
+pub struct Snapshot {
+    rs: Rs,
+    rg: RegGroup,
+    prf: Option<PhysRegFile>,
+    /// Fetch index, the program itself never changes after loading.
+    pc: u32,
+    /// Stores made so far, later ones are undone on restore.
+    stores: usize,
+    rob: Rob,
+    predictor: Box<dyn BranchPredictor>,
+    cycle: u32,
+    /// Length of the history, which only grows while stepping forward.
+    history: usize,
+    stalls: BTreeMap<StallReason, u32>,
+}
+
+impl Snapshot {
+    pub fn capture(t: &Tomasulo) -> Self {
+        Self {
+            rs: t.rs.clone(),
+            rg: t.rg.clone(),
+            prf: t.prf.clone(),
+            pc: t.pc.index,
+            stores: t.mem.stores(),
+            rob: t.rob.clone(),
+            predictor: t.predictor.clone(),
+            cycle: t.cycle,
+            history: t.history.len(),
+            stalls: t.stalls.clone(),
+        }
+    }
+
+    pub fn restore(self, t: &mut Tomasulo) {
+        t.rs = self.rs;
+        t.rg = self.rg;
+        t.prf = self.prf;
+        t.pc.index = self.pc;
+        t.mem.rollback(self.stores);
+        t.rob = self.rob;
+        t.predictor = self.predictor;
+        t.cycle = self.cycle;
+        t.history.truncate(self.history);
+        t.stalls = self.stalls;
+        t.stats.truncate(self.cycle);
+    }
+
+    pub fn cycle(&self) -> u32 {
+        self.cycle
+    }
+}
+
+/// The state before each of the last `capacity` cycles, plus the state right
+/// after loading so that older cycles can still be reached by replaying.
+#[derive(Clone)]
+pub struct Snapshots {
+    pub capacity: usize,
+    ring: VecDeque<Snapshot>,
+    origin: Option<Snapshot>,
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_SNAPSHOTS,
+            ring: VecDeque::new(),
+            origin: None,
+        }
+    }
+}
+
+impl Snapshots {
+    /// Forget every snapshot and remember `origin` as the loaded state.
+    pub fn reset(&mut self, origin: Snapshot) {
+        self.ring.clear();
+        self.origin = Some(origin);
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Record a state, evicting the oldest one when full.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.ring.len() == self.capacity {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(snapshot);
+    }
+
+    /// The most recent state.
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        self.ring.pop_back()
+    }
+
+    pub fn origin(&self) -> Option<&Snapshot> {
+        self.origin.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Snapshot, Snapshots};
+    use crate::comp::Tomasulo;
+
+    #[test]
+    fn ring_evicts_oldest() {
+        let mut t = Tomasulo::default();
+        let mut snapshots = Snapshots {
+            capacity: 2,
+            ..Default::default()
+        };
+        t.init_instruction("add x1 x2 x3").unwrap();
+        for _ in 0..3 {
+            snapshots.push(Snapshot::capture(&t));
+            t.step().unwrap();
+        }
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots.pop().unwrap().cycle(), 2);
+        assert_eq!(snapshots.pop().unwrap().cycle(), 1);
+        assert!(snapshots.pop().is_none());
+    }
+}
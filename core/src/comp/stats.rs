@@ -0,0 +1,177 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use super::config::{ClassCount, MachineConfig};
+use super::rob::Rob;
+use super::rs::{Rs, RsType, StallReason};
+use super::trace::Event;
+use super::CycleReport;
+
+/// How busy the machine was in one cycle, sampled once it ended.
+#[derive(Serialize, Default, Clone, Copy, PartialEq, Debug)]
+pub struct Sample {
+    /// Busy reservation stations per class.
+    pub stations: ClassCount,
+    /// Functional units per class that could not accept an operation.
+    pub units: ClassCount,
+    /// Operations that entered a unit, per class.
+    pub dispatched: ClassCount,
+    /// Cycles the dispatched operations spent in their station since issue.
+    pub waited: ClassCount<u32>,
+    /// Results broadcast.
+    pub broadcasts: usize,
+    /// Finished results that lost CDB arbitration.
+    pub conflicts: usize,
+    pub committed: bool,
+    pub stall: Option<StallReason>,
+}
+
+/// Samples of every simulated cycle. Stepping backward drops the samples of
+/// the undone cycles, so the statistics always describe cycles 1 to now.
+#[derive(Default, Clone, Debug)]
+pub struct Stats {
+    pub samples: Vec<Sample>,
+}
+
+impl Stats {
+    /// Sample the machine at the end of `report.cycle`.
+    pub fn record(&mut self, rs: &Rs, rob: &Rob, report: &CycleReport) {
+        let mut sample = Sample {
+            broadcasts: report.broadcast.len(),
+            conflicts: report.cdb_wait.len(),
+            committed: report.committed.is_some(),
+            stall: report.stall,
+            ..Default::default()
+        };
+        for class in RsType::ALL {
+            *sample.stations.get_mut(class) = rs.slots(class).iter().filter(|v| v.busy).count();
+            *sample.units.get_mut(class) = rs.units.get(class).busy(report.cycle);
+        }
+        report.events.iter().for_each(|v| {
+            if let Event::Dispatch { tag, class, .. } = *v {
+                *sample.dispatched.get_mut(class) += 1;
+                *sample.waited.get_mut(class) += report.cycle - rob.get(tag).timing.issue;
+            }
+        });
+        self.samples.push(sample);
+    }
+
+    /// Forget the samples after `cycle`.
+    pub fn truncate(&mut self, cycle: u32) {
+        self.samples.truncate(cycle as usize);
+    }
+
+    /// Summarize the samples of a machine built from `config`.
+    pub fn summary(&self, config: &MachineConfig) -> Summary {
+        let cycles = self.samples.len();
+        let committed = self.samples.iter().filter(|v| v.committed).count();
+        let sum = |f: &dyn Fn(&Sample) -> usize| self.samples.iter().map(f).sum::<usize>();
+        let ratio = |n: usize, d: usize| if d == 0 { 0.0 } else { n as f64 / d as f64 };
+        let mut summary = Summary {
+            cycles,
+            committed,
+            ipc: ratio(committed, cycles),
+            cdb_utilization: ratio(sum(&|v| v.broadcasts), config.cdb * cycles),
+            cdb_conflicts: sum(&|v| v.conflicts),
+            ..Default::default()
+        };
+        for class in RsType::ALL {
+            let dispatched = sum(&|v| *v.dispatched.get(class));
+            *summary.occupancy.get_mut(class) = ratio(
+                sum(&|v| *v.stations.get(class)),
+                config.stations.get(class) * cycles,
+            );
+            *summary.utilization.get_mut(class) = ratio(
+                sum(&|v| *v.units.get(class)),
+                config.units.get(class) * cycles,
+            );
+            *summary.wait.get_mut(class) =
+                ratio(sum(&|v| *v.waited.get(class) as usize), dispatched);
+        }
+        self.samples
+            .iter()
+            .filter_map(|v| v.stall)
+            .for_each(|v| *summary.stalls.entry(v).or_default() += 1);
+        summary
+    }
+}
+
+/// Totals and averages over every sampled cycle. Fractions are in 0..=1.
+#[derive(Serialize, Default, Clone, PartialEq, Debug)]
+pub struct Summary {
+    pub cycles: usize,
+    pub committed: usize,
+    pub ipc: f64,
+    /// Average fraction of the stations of each class that were busy.
+    pub occupancy: ClassCount<f64>,
+    /// Average fraction of the units of each class that were busy.
+    pub utilization: ClassCount<f64>,
+    /// Average cycles an operation waited in its station before executing.
+    pub wait: ClassCount<f64>,
+    /// Fraction of the bus slots that carried a result.
+    pub cdb_utilization: f64,
+    /// Results that lost arbitration, counted once per cycle of waiting.
+    pub cdb_conflicts: usize,
+    pub stalls: BTreeMap<StallReason, usize>,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "cycles\t{}", self.cycles)?;
+        writeln!(f, "committed\t{}", self.committed)?;
+        writeln!(f, "ipc\t{:.3}", self.ipc)?;
+        writeln!(f, "class\toccupancy\tunits busy\tavg wait")?;
+        for class in RsType::ALL {
+            writeln!(
+                f,
+                "  {}\t{:.1}%\t{:.1}%\t{:.2}",
+                class,
+                self.occupancy.get(class) * 100.0,
+                self.utilization.get(class) * 100.0,
+                self.wait.get(class)
+            )?;
+        }
+        writeln!(f, "cdb busy\t{:.1}%", self.cdb_utilization * 100.0)?;
+        writeln!(f, "cdb conflicts\t{}", self.cdb_conflicts)?;
+        writeln!(f, "stalls\t{}", self.stalls.values().sum::<usize>())?;
+        self.stalls
+            .iter()
+            .try_for_each(|(reason, n)| writeln!(f, "  {}\t{}", reason, n))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::comp::config::MachineConfig;
+    use crate::comp::rs::{RsType, StallReason};
+    use crate::comp::Tomasulo;
+
+    #[test]
+    fn summary() {
+        let mut t = Tomasulo::default();
+        t.init_instruction("mul x1 x2 x3\nmul x4 x5 x6\nmul x7 x5 x6\nadd x8 x1 x4")
+            .unwrap();
+        t.run_to_completion(100).unwrap();
+        let summary = t.stats.summary(&t.config);
+        assert_eq!(summary.cycles, t.cycle() as usize);
+        assert_eq!(summary.committed, 4);
+        assert_eq!(
+            summary.stalls[&StallReason::NoStation(RsType::Mul)],
+            t.stalls[&StallReason::NoStation(RsType::Mul)] as usize
+        );
+        // one unpipelined multiplier executes the three muls back to back
+        assert!(summary.utilization.mul > 0.75);
+        assert_eq!(summary.utilization.load, 0.0);
+        // the later muls wait for the multiplier, the add for both products
+        assert!(summary.wait.mul > 1.0);
+        assert!(summary.wait.add > summary.wait.mul);
+        assert!(summary.cdb_utilization > 0.0 && summary.cdb_utilization < 1.0);
+        assert!(summary.to_string().starts_with("cycles\t"));
+
+        t.goto_cycle(3).unwrap();
+        let summary = t.stats.summary(&MachineConfig::default());
+        assert_eq!(summary.cycles, 3);
+        assert_eq!(t.stats.samples[2].stations.mul, 2);
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::io::Write;
+
+use super::pc::Instrution;
+use super::reg::RegIndex;
+use super::rob::RobTag;
+use super::rs::{RsType, StallReason};
+
+/// Something that happened to an instruction or the machine in a cycle.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Issue {
+        tag: RobTag,
+        pc: u32,
+        instr: Instrution,
+    },
+    /// The instruction entered a functional unit.
+    Dispatch {
+        tag: RobTag,
+        class: RsType,
+        unit: usize,
+    },
+    /// A cycle of execution, with the cycles left after it.
+    ExecTick {
+        tag: RobTag,
+        remaining: u8,
+    },
+    /// Execution finished.
+    Complete {
+        tag: RobTag,
+    },
+    Broadcast {
+        tag: RobTag,
+        value: i32,
+    },
+    /// A committed value reached the architectural register file.
+    RegisterWrite {
+        tag: RobTag,
+        reg: RegIndex,
+        fp: bool,
+        value: i32,
+    },
+    /// Nothing was issued although instructions were left to fetch.
+    Stall {
+        reason: StallReason,
+    },
+    Commit {
+        tag: RobTag,
+        pc: u32,
+        instr: Instrution,
+    },
+    /// Every instruction in flight was squashed and fetching restarts at `pc`.
+    Flush {
+        pc: u32,
+    },
+}
+
+/// Receives the events of every simulated cycle, in the order they happened.
+pub trait Subscriber {
+    fn event(&mut self, cycle: u32, event: &Event) -> Result<()>;
+}
+
+/// The subscribers of a simulator. Cloning a simulator does not clone them,
+/// the copy starts with none.
+#[derive(Default)]
+pub struct Subscribers(pub Vec<Box<dyn Subscriber>>);
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Subscribers {
+    pub fn publish(&mut self, cycle: u32, events: &[Event]) -> Result<()> {
+        for subscriber in self.0.iter_mut() {
+            events
+                .iter()
+                .try_for_each(|event| subscriber.event(cycle, event))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    cycle: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Writes one JSON object per event and line, such as
+/// `{"cycle":3,"event":"broadcast","tag":0,"value":5}`.
+pub struct JsonlWriter<W: Write> {
+    pub out: W,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Subscriber for JsonlWriter<W> {
+    fn event(&mut self, cycle: u32, event: &Event) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &Line { cycle, event })?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, JsonlWriter, StallReason, Subscriber};
+
+    #[test]
+    fn jsonl() {
+        let mut writer = JsonlWriter::new(Vec::new());
+        writer
+            .event(3, &Event::Broadcast { tag: 0, value: 5 })
+            .unwrap();
+        writer
+            .event(
+                4,
+                &Event::Stall {
+                    reason: StallReason::RobFull,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(writer.out).unwrap(),
+            "{\"cycle\":3,\"event\":\"broadcast\",\"tag\":0,\"value\":5}\n{\"cycle\":4,\"event\":\"stall\",\"reason\":\"rob_full\"}\n"
+        );
+    }
+}
@@ -1,19 +1,141 @@
-use anyhow::Result;
-use core::comp::rs::RS;
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use core::comp::asm::Diagnostics;
+use core::comp::config::MachineConfig;
+use core::comp::golden::{self, Golden};
+use core::comp::trace::JsonlWriter;
 use core::comp::Tomasulo;
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    let rs = RS.write().unwrap();
-    println!("{}", rs);
-    drop(rs);
-    let mut file = File::open("test/1.s")?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let tomasulo = Tomasulo::default();
-    tomasulo.init_instruction(&contents)?;
-    tomasulo.run_to(10);
+/// The program assembled with errors.
+const EXIT_ASSEMBLER: u8 = 3;
+/// The program did not drain within the cycle budget.
+const EXIT_TIMEOUT: u8 = 4;
+/// The final state differs from the golden model.
+const EXIT_DIVERGED: u8 = 5;
 
-    Ok(())
+/// Headless Tomasulo simulator.
+#[derive(Parser)]
+#[command(name = "tomasulo")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Assemble a program and run it until it drains.
+    Run(RunArgs),
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Assembly source.
+    program: PathBuf,
+    /// Machine configuration in TOML, or JSON with a .json extension, the
+    /// defaults otherwise.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Initial memory words from address 0, separated by whitespace. They
+    /// replace those of the configuration.
+    #[arg(long)]
+    mem: Option<PathBuf>,
+    /// Give up after this many cycles.
+    #[arg(long, default_value_t = 100_000)]
+    cycles: u32,
+    /// Write every event as a line of JSON.
+    #[arg(long)]
+    trace: Option<PathBuf>,
+    /// Print the register files once the run ends.
+    #[arg(long)]
+    dump_regs: bool,
+    /// Print the non-zero memory words once the run ends.
+    #[arg(long)]
+    dump_mem: bool,
+    /// Compare the final state with the sequential golden model.
+    #[arg(long)]
+    check: bool,
+}
+
+fn run(args: &RunArgs) -> Result<ExitCode> {
+    let mut t = Tomasulo::default();
+    if let Some(path) = &args.config {
+        t.config = MachineConfig::from_file(path)
+            .with_context(|| format!("Bad configuration {}", path.display()))?;
+    }
+    if let Some(path) = &args.mem {
+        let src =
+            fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        t.config.mem_words = src
+            .split_whitespace()
+            .map(|v| {
+                v.parse()
+                    .with_context(|| format!("Bad word {} in {}", v, path.display()))
+            })
+            .collect::<Result<_>>()?;
+    }
+    let src = fs::read_to_string(&args.program)
+        .with_context(|| format!("Cannot read {}", args.program.display()))?;
+    if let Err(e) = t.init_instruction(&src) {
+        let Some(diagnostics) = e.downcast_ref::<Diagnostics>() else {
+            return Err(e);
+        };
+        diagnostics
+            .0
+            .iter()
+            .for_each(|v| eprintln!("{}:{}", args.program.display(), v));
+        return Ok(ExitCode::from(EXIT_ASSEMBLER));
+    }
+    if let Some(path) = &args.trace {
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        t.subscribe(JsonlWriter::new(BufWriter::new(file)));
+    }
+
+    // nothing steps back, so keeping snapshots would only slow the run down
+    t.snapshots.capacity = 0;
+    while !t.is_drained() && t.cycle() < args.cycles {
+        t.step()?;
+    }
+    print!("{}", t.stats.summary(&t.config));
+    if args.dump_regs {
+        for (i, (x, f)) in t.rg.regs.iter().zip(t.rg.fregs.iter()).enumerate() {
+            println!(
+                "x{}\t{}\tf{}\t{}",
+                i,
+                x.value,
+                i,
+                f32::from_bits(f.value as u32)
+            );
+        }
+    }
+    if args.dump_mem {
+        t.mem
+            .words()
+            .filter(|(_, value)| *value != 0)
+            .for_each(|(addr, value)| println!("{:#06x}\t{}", addr, value));
+    }
+    if !t.is_drained() {
+        eprintln!("Program did not finish within {} cycles", args.cycles);
+        return Ok(ExitCode::from(EXIT_TIMEOUT));
+    }
+    if args.check {
+        let mut golden = Golden::new(&t);
+        golden.run(args.cycles as u64)?;
+        let divergences = golden::compare(&t, &golden);
+        divergences.iter().for_each(|v| eprintln!("{}", v));
+        if !divergences.is_empty() {
+            return Ok(ExitCode::from(EXIT_DIVERGED));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> Result<ExitCode> {
+    match Cli::parse().command {
+        Command::Run(args) => run(&args),
+    }
 }
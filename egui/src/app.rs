@@ -1,7 +1,19 @@
-use core::comp::{rs::Slot, Tomasulo};
+use core::comp::{
+    config::MachineConfig,
+    dataflow::{Dataflow, Node},
+    lsq::Lsq,
+    mem::Mem,
+    reg::{Reg, RegGroup},
+    rename::PhysRegFile,
+    rob::{Rob, Stage},
+    rs::Rs,
+    rs::{RsType, Slot},
+    stats::Stats,
+    CycleReport, Tomasulo,
+};
 
-use anyhow::Result;
-use egui::{Color32, Context, RichText, Window};
+use anyhow::{anyhow, Result};
+use egui::{Color32, Context, RichText, Ui, Window};
 use egui_extras::{Column, TableBody, TableBuilder};
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
@@ -11,13 +23,45 @@ pub struct TemplateApp {
     // Example stuff:
     label: String,
     instructions: String,
+    /// Machine description in TOML.
+    config: String,
 
     #[serde(skip)] // This how you opt-out of serialization of a field
     value: i32,
+    #[serde(skip)]
+    config_error: String,
+    #[serde(skip)]
+    asm_error: String,
+    /// Error raised while simulating, e.g. an access outside memory.
+    #[serde(skip)]
+    run_error: String,
     #[serde(skip)] // This how you opt-out of serialization of a field
     tomasulo: Tomasulo,
+    /// Last cycle simulated by `next`.
+    #[serde(skip)]
+    report: CycleReport,
+    /// The program or machine was edited since it was last loaded.
+    #[serde(skip)]
+    stale: bool,
+    /// Where the run in progress is going.
+    #[serde(skip)]
+    target: Option<Target>,
 }
 
+/// A run spread over frames, so that the window stays responsive.
+#[derive(Clone, Copy)]
+enum Target {
+    /// A cycle picked with the step controls.
+    Cycle(u32),
+    /// The end of the program, giving up at the cycle held.
+    Drained(u32),
+}
+
+/// Cycles the run button may take before giving up.
+const RUN_BUDGET: u32 = 100_000;
+/// Cycles run per frame.
+const RUN_CHUNK: u32 = 1_000;
+
 impl Default for TemplateApp {
     fn default() -> Self {
         Self {
@@ -31,8 +75,15 @@ add x5 x7 x8
 add x5 x9 x1
 "#
             .to_owned(),
+            config: MachineConfig::default().to_toml(),
             value: 0,
+            config_error: String::new(),
+            asm_error: String::new(),
+            run_error: String::new(),
             tomasulo: Tomasulo::default(),
+            report: CycleReport::default(),
+            stale: true,
+            target: None,
         }
     }
 }
@@ -64,9 +115,14 @@ impl TemplateApp {
             // .default_size([300.0, 350.0])
             .show(ctx, |ui| {
                 ui.label("Instructions");
-                egui::TextEdit::multiline(&mut self.instructions)
+                self.stale |= egui::TextEdit::multiline(&mut self.instructions)
                     .hint_text("Type something!")
-                    .show(ui);
+                    .show(ui)
+                    .response
+                    .changed();
+                if !self.asm_error.is_empty() {
+                    ui.label(RichText::new(&self.asm_error).color(Color32::RED));
+                }
 
                 let table = TableBuilder::new(ui)
                     .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
@@ -88,7 +144,7 @@ impl TemplateApp {
                     //     });
                     // })
                     .body(|mut body| {
-                        let pc = core::comp::pc::PC.read().unwrap();
+                        let pc = &self.tomasulo.pc;
                         pc.instrutions.iter().enumerate().for_each(|(i, v)| {
                             if i as u32 == pc.index {
                                 body.row(18.0, |mut row| {
@@ -136,14 +192,188 @@ impl TemplateApp {
                                 });
                             }
                         });
-                    })
+                    });
+
+                ui.separator();
+                ui.label("Status");
+                ui.push_id("status", |ui| {
+                    let table = TableBuilder::new(ui)
+                        .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                        .column(Column::auto())
+                        .column(Column::auto())
+                        .column(Column::auto())
+                        .column(Column::auto())
+                        .column(Column::auto())
+                        .column(Column::auto())
+                        .min_scrolled_height(0.0)
+                        .max_scroll_height(300.0);
+                    table
+                        .header(20.0, |mut header| {
+                            header.col(|ui| {
+                                ui.strong("Instruction");
+                            });
+                            header.col(|ui| {
+                                ui.strong("Issue");
+                            });
+                            header.col(|ui| {
+                                ui.strong("Exec start");
+                            });
+                            header.col(|ui| {
+                                ui.strong("Exec complete");
+                            });
+                            header.col(|ui| {
+                                ui.strong("Write result");
+                            });
+                            header.col(|ui| {
+                                ui.strong("Commit");
+                            });
+                        })
+                        .body(|body| {
+                            // only the rows in view are built
+                            let t = &self.tomasulo;
+                            body.rows(18.0, t.instr_count(), |mut row| {
+                                let Some(v) = t.instr_status_iter().nth(row.index()) else {
+                                    return;
+                                };
+                                let instr = v.instr.to_tuple();
+                                let text =
+                                    format!("{} {} {} {}", instr.0, instr.1, instr.2, instr.3);
+                                row.col(|ui| {
+                                    if v.squashed {
+                                        ui.label(RichText::new(text).strikethrough().weak());
+                                    } else {
+                                        ui.label(text);
+                                    }
+                                });
+                                [
+                                    Some(v.timing.issue),
+                                    v.timing.exec_start,
+                                    v.timing.exec_complete,
+                                    v.timing.write_result,
+                                    v.timing.commit,
+                                ]
+                                .into_iter()
+                                .for_each(|cycle| {
+                                    row.col(|ui| {
+                                        if let Some(cycle) = cycle {
+                                            ui.label(cycle.to_string());
+                                        } else {
+                                            ui.label("");
+                                        }
+                                    });
+                                });
+                            });
+                        })
+                });
+            });
+    }
+    fn machine(&mut self, ctx: &Context) {
+        Window::new("Machine")
+            .open(&mut true)
+            .vscroll(true)
+            .resizable(true)
+            .title_bar(false)
+            .show(ctx, |ui| {
+                ui.label("Machine");
+                self.stale |= egui::TextEdit::multiline(&mut self.config)
+                    .code_editor()
+                    .show(ui)
+                    .response
+                    .changed();
+                if !self.config_error.is_empty() {
+                    ui.label(RichText::new(&self.config_error).color(Color32::RED));
+                }
             });
     }
     fn run(&mut self) -> Result<()> {
-        self.tomasulo.init_instruction(&self.instructions)?;
-        self.tomasulo.run_to(self.value);
+        match MachineConfig::from_toml(&self.config) {
+            Ok(config) => {
+                self.config_error.clear();
+                self.tomasulo.config = config;
+            }
+            Err(e) => {
+                self.config_error = e.to_string();
+                return Err(e);
+            }
+        }
+        if let Err(e) = self.tomasulo.init_instruction(&self.instructions) {
+            self.asm_error = e.to_string();
+            return Err(e);
+        }
+        self.asm_error.clear();
+        self.stale = false;
+        self.report = CycleReport::default();
+        self.target = Some(Target::Cycle(self.value as u32));
         Ok(())
     }
+    /// Move the loaded machine to cycle `value`, reloading it first if it
+    /// was edited. Going backward restores snapshots instead of replaying,
+    /// the cycles left to run go on over the next frames.
+    fn goto(&mut self) -> Result<()> {
+        self.target = None;
+        if self.stale {
+            return self.run();
+        }
+        let cycle = self.value as u32;
+        if self.tomasulo.cycle() + 1 == cycle {
+            self.report = self.tomasulo.step()?;
+        } else {
+            self.report = CycleReport::default();
+            self.tomasulo.rewind(cycle)?;
+            self.target = Some(Target::Cycle(cycle));
+        }
+        Ok(())
+    }
+    /// Keep the error of a control, if any. Assembler and configuration
+    /// errors are already shown next to their editors.
+    fn show_error(&mut self, result: Result<()>) {
+        self.run_error = match result {
+            Err(e) if self.asm_error.is_empty() && self.config_error.is_empty() => e.to_string(),
+            _ => String::new(),
+        };
+    }
+    /// Start running the loaded program until it drains. The run goes on
+    /// over the next frames in [`RUN_CHUNK`] cycles at a time.
+    fn finish(&mut self) -> Result<()> {
+        if self.stale {
+            self.value = 0;
+            self.run()?;
+        }
+        self.target = Some(Target::Drained(self.tomasulo.cycle() + RUN_BUDGET));
+        Ok(())
+    }
+    /// Run the next [`RUN_CHUNK`] cycles towards the target, if any.
+    fn run_chunk(&mut self) -> Result<()> {
+        let Some(target) = self.target else {
+            return Ok(());
+        };
+        let t = &mut self.tomasulo;
+        let result = match target {
+            Target::Cycle(end) => {
+                let stop = (t.cycle() + RUN_CHUNK).min(end);
+                t.run_to(stop.saturating_sub(t.cycle()) as i32)
+            }
+            Target::Drained(end) => {
+                let stop = (t.cycle() + RUN_CHUNK).min(end);
+                t.run_until(|t| t.cycle() >= stop).map(|_| ())
+            }
+        };
+        self.value = t.cycle() as i32;
+        let done = match target {
+            Target::Cycle(end) => t.cycle() >= end,
+            Target::Drained(end) => t.is_drained() || t.cycle() >= end,
+        };
+        if result.is_err() || done {
+            self.target = None;
+        }
+        if result.is_ok() && matches!(target, Target::Drained(_)) && done && !t.is_drained() {
+            return Err(anyhow!(
+                "Program did not finish within {} cycles",
+                RUN_BUDGET
+            ));
+        }
+        result
+    }
 }
 
 impl eframe::App for TemplateApp {
@@ -186,16 +416,45 @@ impl eframe::App for TemplateApp {
             // });
 
             // ui.add(egui::Slider::new(&mut self.value, 0.0..=10.0).text("value"));
+            if self.target.is_some() {
+                let result = self.run_chunk();
+                self.show_error(result);
+                ctx.request_repaint();
+            }
             self.instruction(ctx);
+            self.machine(ctx);
             ui.horizontal(|ui| {
                 if ui.button("prev").clicked() && self.value > 0 {
                     self.value -= 1;
-                    let _ = self.run();
+                    let result = self.goto();
+                    self.show_error(result);
+                }
+                if ui
+                    .add(egui::DragValue::new(&mut self.value).clamp_range(0..=i32::MAX))
+                    .changed()
+                {
+                    let result = self.goto();
+                    self.show_error(result);
                 }
-                ui.label(self.value.to_string());
                 if ui.button("next").clicked() {
                     self.value += 1;
-                    let _ = self.run();
+                    let result = self.goto();
+                    self.show_error(result);
+                }
+                if self.target.is_some() {
+                    if ui.button("stop").clicked() {
+                        self.target = None;
+                    }
+                } else if ui.button("run").clicked() {
+                    let result = self.finish();
+                    self.show_error(result);
+                }
+                if !self.run_error.is_empty() {
+                    ui.label(RichText::new(&self.run_error).color(Color32::RED));
+                } else if self.tomasulo.is_drained() && !self.stale {
+                    ui.label("done");
+                } else if let Some(stall) = &self.report.stall {
+                    ui.label(RichText::new(format!("stall: {}", stall)).color(Color32::YELLOW));
                 }
             });
 
@@ -212,8 +471,17 @@ impl eframe::App for TemplateApp {
             });
             //     new_windows(ctx);
         });
-        rs(ctx);
-        regs(ctx);
+        rs(ctx, &self.tomasulo.rs);
+        rob(ctx, &self.tomasulo.rob);
+        lsq(ctx, &self.tomasulo.rs.lsq);
+        regs(ctx, &self.tomasulo.rg);
+        if let Some(prf) = &self.tomasulo.prf {
+            physical_regs(ctx, prf);
+        }
+        mem(ctx, &self.tomasulo.mem);
+        stats(ctx, &self.tomasulo.stats, &self.tomasulo.config);
+        timeline(ctx, &self.tomasulo);
+        dataflow(ctx, &self.tomasulo);
     }
 }
 
@@ -245,7 +513,7 @@ fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
 //         .show(ctx, |ui| ui.label("Powered by "));
 // }
 
-fn rs(ctx: &Context) {
+fn rs(ctx: &Context, rs: &Rs) {
     Window::new("Reservation station")
         .open(&mut true)
         .title_bar(false)
@@ -254,6 +522,14 @@ fn rs(ctx: &Context) {
         // .default_size([300.0, 350.0])
         .show(ctx, |ui| {
             ui.label("Reservation station");
+            let stats = rs.cdb.stats;
+            ui.label(format!(
+                "CDB: {} bus(es), {}, {} broadcasts, {} conflicts",
+                rs.cdb.buses,
+                rs.cdb.arbiter.name(),
+                stats.broadcasts,
+                stats.conflicts
+            ));
 
             let table = TableBuilder::new(ui)
                 // .striped(self.striped)
@@ -268,6 +544,7 @@ fn rs(ctx: &Context) {
                 .column(Column::auto())
                 .column(Column::auto())
                 .column(Column::auto())
+                .column(Column::auto())
                 // .column(Column::initial(100.0).range(40.0..=300.0))
                 // .column(Column::initial(100.0).at_least(40.0).clip(true))
                 // .column(Column::remainder())
@@ -283,6 +560,9 @@ fn rs(ctx: &Context) {
                     header.col(|ui| {
                         ui.strong("Time");
                     });
+                    header.col(|ui| {
+                        ui.strong("Unit");
+                    });
                     header.col(|ui| {
                         ui.strong("Addr");
                     });
@@ -303,7 +583,6 @@ fn rs(ctx: &Context) {
                     });
                 })
                 .body(|mut body| {
-                    let rs = core::comp::rs::RS.read().unwrap();
                     display(&mut body, &rs.add, "add".to_owned());
                     display(&mut body, &rs.mul, "mul".to_owned());
                     display(&mut body, &rs.load, "load".to_owned());
@@ -311,52 +590,174 @@ fn rs(ctx: &Context) {
                 })
         });
 }
-fn regs(ctx: &Context) {
-    Window::new("Reg Group")
+fn rob(ctx: &Context, rob: &Rob) {
+    Window::new("Reorder buffer")
         .open(&mut true)
         .title_bar(false)
         .vscroll(false)
         .resizable(true)
-        // .default_size([700.0, 750.0])
         .show(ctx, |ui| {
-            ui.label("Reg Group");
+            ui.label("Reorder buffer");
 
             let table = TableBuilder::new(ui)
-                // .striped(self.striped)
-                // .resizable(self.resizable)
                 .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
                 .column(Column::auto())
                 .column(Column::auto())
                 .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
                 .min_scrolled_height(0.0);
             table
                 .header(20.0, |mut header| {
                     header.col(|ui| {
-                        ui.strong("Reg Index");
+                        ui.strong("Entry");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Busy");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Instruction");
+                    });
+                    header.col(|ui| {
+                        ui.strong("State");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Dest");
                     });
                     header.col(|ui| {
                         ui.strong("Value");
                     });
+                })
+                .body(|mut body| {
+                    rob.entries.iter().enumerate().for_each(|(i, v)| {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                // the head is the next entry to commit
+                                if i == rob.head && v.busy {
+                                    ui.label(
+                                        RichText::new("rob".to_owned() + &i.to_string())
+                                            .color(Color32::from_rgb(110, 255, 110)),
+                                    );
+                                } else {
+                                    ui.label("rob".to_owned() + &i.to_string());
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(v.busy.to_string());
+                            });
+                            row.col(|ui| {
+                                if let Some(instr) = v.instr {
+                                    let t = instr.to_tuple();
+                                    ui.label(format!("{} {} {} {}", t.0, t.1, t.2, t.3));
+                                } else {
+                                    ui.label("");
+                                }
+                            });
+                            row.col(|ui| {
+                                if v.busy {
+                                    ui.label(v.state.to_string());
+                                } else {
+                                    ui.label("");
+                                }
+                            });
+                            row.col(|ui| {
+                                if let Some(dest) = v.dest {
+                                    let file = if v.fp { "f" } else { "x" };
+                                    ui.label(file.to_owned() + &dest.to_string());
+                                } else if let Some(addr) = v.addr {
+                                    ui.label(format!("mem[{}]", addr));
+                                } else {
+                                    ui.label("");
+                                }
+                            });
+                            row.col(|ui| {
+                                if let Some(value) = v.value {
+                                    if v.fp {
+                                        ui.label(f32::from_bits(value as u32).to_string());
+                                    } else {
+                                        ui.label(value.to_string());
+                                    }
+                                } else {
+                                    ui.label("");
+                                }
+                            });
+                        });
+                    });
+                })
+        });
+}
+
+fn lsq(ctx: &Context, lsq: &Lsq) {
+    Window::new("Load/store queue")
+        .open(&mut true)
+        .title_bar(false)
+        .vscroll(false)
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.label("Load/store queue");
+            ui.label(format!(
+                "{:?}, {} forwarded, {} replayed",
+                lsq.mode, lsq.forwards, lsq.replays
+            ));
+
+            let table = TableBuilder::new(ui)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .min_scrolled_height(0.0);
+            table
+                .header(20.0, |mut header| {
+                    header.col(|ui| {
+                        ui.strong("Entry");
+                    });
                     header.col(|ui| {
-                        ui.strong("Status");
+                        ui.strong("Kind");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Addr");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Data");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Source");
                     });
                 })
                 .body(|mut body| {
-                    let rg = core::comp::reg::REG_GROUP.read().unwrap();
-                    rg.regs.iter().enumerate().for_each(|(i, v)| {
+                    lsq.entries.iter().for_each(|v| {
                         body.row(18.0, |mut row| {
                             row.col(|ui| {
-                                ui.label("x".to_owned() + &i.to_string());
+                                ui.label(format!("rob{}", v.tag));
                             });
                             row.col(|ui| {
-                                ui.label(v.value.to_string());
+                                ui.label(if v.store { "store" } else { "load" });
                             });
                             row.col(|ui| {
-                                if let Some(state) = v.state {
-                                    ui.label(format!("{}{}", state.0, state.1))
+                                if let Some(addr) = v.addr {
+                                    ui.label(addr.to_string());
                                 } else {
-                                    ui.label("")
-                                };
+                                    ui.label("");
+                                }
+                            });
+                            row.col(|ui| {
+                                if let Some(data) = v.data {
+                                    ui.label(data.to_string());
+                                } else {
+                                    ui.label("");
+                                }
+                            });
+                            row.col(|ui| {
+                                if let Some(source) = v.source {
+                                    ui.label(format!("rob{}", source));
+                                } else if v.performed {
+                                    ui.label("memory");
+                                } else {
+                                    ui.label("");
+                                }
                             });
                         });
                     });
@@ -364,6 +765,548 @@ fn regs(ctx: &Context) {
         });
 }
 
+fn regs(ctx: &Context, rg: &RegGroup) {
+    Window::new("Reg Group")
+        .open(&mut true)
+        .title_bar(false)
+        .vscroll(false)
+        .resizable(true)
+        // .default_size([700.0, 750.0])
+        .show(ctx, |ui| {
+            ui.label("Reg Group");
+
+            let table = TableBuilder::new(ui)
+                // .striped(self.striped)
+                // .resizable(self.resizable)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .columns(Column::auto(), 6)
+                .min_scrolled_height(0.0);
+            table
+                .header(20.0, |mut header| {
+                    for _ in 0..2 {
+                        header.col(|ui| {
+                            ui.strong("Reg Index");
+                        });
+                        header.col(|ui| {
+                            ui.strong("Value");
+                        });
+                        header.col(|ui| {
+                            ui.strong("Status");
+                        });
+                    }
+                })
+                .body(|mut body| {
+                    // the integer and FP files side by side
+                    rg.regs
+                        .iter()
+                        .zip(rg.fregs.iter())
+                        .enumerate()
+                        .for_each(|(i, (x, f))| {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label("x".to_owned() + &i.to_string());
+                                });
+                                row.col(|ui| {
+                                    ui.label(x.value.to_string());
+                                });
+                                row.col(|ui| {
+                                    reg_state(ui, x);
+                                });
+                                row.col(|ui| {
+                                    ui.label("f".to_owned() + &i.to_string());
+                                });
+                                row.col(|ui| {
+                                    ui.label(f32::from_bits(f.value as u32).to_string());
+                                });
+                                row.col(|ui| {
+                                    reg_state(ui, f);
+                                });
+                            });
+                        });
+                })
+        });
+}
+
+/// The register alias tables and the physical register each architectural
+/// register currently maps to.
+fn physical_regs(ctx: &Context, prf: &PhysRegFile) {
+    Window::new("Physical registers")
+        .open(&mut true)
+        .title_bar(false)
+        .vscroll(false)
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.label("Physical registers");
+            ui.label(format!(
+                "free ({}): {}",
+                prf.free.len(),
+                prf.free
+                    .iter()
+                    .map(|v| format!("p{}", v))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+
+            let table = TableBuilder::new(ui)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .columns(Column::auto(), 5)
+                .min_scrolled_height(0.0)
+                .max_scroll_height(300.0);
+            table
+                .header(20.0, |mut header| {
+                    ["Reg", "RAT", "Committed", "Value", "Status"]
+                        .iter()
+                        .for_each(|v| {
+                            header.col(|ui| {
+                                ui.strong(*v);
+                            });
+                        });
+                })
+                .body(|mut body| {
+                    let count = prf.rat.len() / 2;
+                    (0..prf.rat.len()).for_each(|arch| {
+                        let phys = &prf.regs[prf.rat[arch]];
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                if arch < count {
+                                    ui.label(format!("x{}", arch));
+                                } else {
+                                    ui.label(format!("f{}", arch - count));
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("p{}", prf.rat[arch]));
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("p{}", prf.retirement_rat[arch]));
+                            });
+                            row.col(|ui| {
+                                if !phys.ready {
+                                    ui.label("");
+                                } else if arch < count {
+                                    ui.label(phys.value.to_string());
+                                } else {
+                                    ui.label(f32::from_bits(phys.value as u32).to_string());
+                                }
+                            });
+                            row.col(|ui| {
+                                reg_state(ui, &phys.into());
+                            });
+                        });
+                    });
+                })
+        });
+}
+
+fn reg_state(ui: &mut Ui, reg: &Reg) {
+    if let Some(state) = reg.state {
+        ui.label(format!("rob{}", state));
+    } else {
+        ui.label("");
+    }
+}
+
+fn mem(ctx: &Context, mem: &Mem) {
+    Window::new("Memory")
+        .open(&mut true)
+        .title_bar(false)
+        .vscroll(false)
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.label("Memory");
+
+            let table = TableBuilder::new(ui)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto())
+                .column(Column::auto())
+                .min_scrolled_height(0.0)
+                .max_scroll_height(300.0);
+            table
+                .header(20.0, |mut header| {
+                    header.col(|ui| {
+                        ui.strong("Addr");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Value");
+                    });
+                })
+                .body(|mut body| {
+                    (0..mem.size() / 4).for_each(|i| {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label(format!("{:#06x}", i * 4));
+                            });
+                            row.col(|ui| {
+                                ui.label(mem.load_word(i as i32 * 4).unwrap_or(0).to_string());
+                            });
+                        });
+                    });
+                })
+        });
+}
+
+/// Every dynamic instruction as a row and every cycle as a column, colored
+/// by what the instruction was doing.
+fn timeline(ctx: &Context, t: &Tomasulo) {
+    const LABEL: f32 = 140.0;
+    const CELL: f32 = 14.0;
+    const ROW: f32 = 18.0;
+    Window::new("Timeline")
+        .open(&mut true)
+        .title_bar(false)
+        .vscroll(false)
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.label("Timeline");
+            ui.horizontal_wrapped(|ui| {
+                STAGES.iter().for_each(|&stage| {
+                    ui.label(RichText::new("■").color(stage_color(stage)));
+                    ui.label(stage.to_string());
+                });
+            });
+
+            // only the rows and cycles in view are drawn, long runs have
+            // thousands of each
+            let count = t.instr_count();
+            egui::ScrollArea::both()
+                .max_height(300.0)
+                .show_viewport(ui, |ui, viewport| {
+                    let size =
+                        egui::vec2(LABEL + CELL * t.cycle() as f32, ROW * (count + 1) as f32);
+                    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+                    let painter = ui.painter_at(rect);
+                    let font = egui::FontId::monospace(11.0);
+                    let text = ui.visuals().text_color();
+                    let cell_left = |cycle: u32| rect.left() + LABEL + CELL * (cycle - 1) as f32;
+                    let first = ((viewport.min.x - LABEL) / CELL).max(0.0) as u32 + 1;
+                    let last =
+                        (((viewport.max.x - LABEL) / CELL).max(0.0) as u32 + 1).min(t.cycle());
+                    let cycles = first..=last;
+                    cycles
+                        .clone()
+                        .filter(|v| *v == 1 || v % 5 == 0)
+                        .for_each(|cycle| {
+                            painter.text(
+                                egui::pos2(cell_left(cycle) + CELL / 2.0, rect.top() + ROW / 2.0),
+                                egui::Align2::CENTER_CENTER,
+                                cycle.to_string(),
+                                font.clone(),
+                                text,
+                            );
+                        });
+                    // row 0 holds the cycle numbers
+                    let rows = (viewport.min.y / ROW).max(1.0) as usize - 1;
+                    let visible = (viewport.height() / ROW) as usize + 2;
+                    t.instr_status_iter()
+                        .enumerate()
+                        .skip(rows)
+                        .take(visible)
+                        .for_each(|(i, v)| {
+                            let top = rect.top() + ROW * (i + 1) as f32;
+                            painter.text(
+                                egui::pos2(rect.left(), top + ROW / 2.0),
+                                egui::Align2::LEFT_CENTER,
+                                v.instr.to_string(),
+                                font.clone(),
+                                if v.squashed {
+                                    ui.visuals().weak_text_color()
+                                } else {
+                                    text
+                                },
+                            );
+                            cycles.clone().for_each(|cycle| {
+                                let Some(stage) = v.timing.stage(cycle) else {
+                                    return;
+                                };
+                                let cell = egui::Rect::from_min_size(
+                                    egui::pos2(cell_left(cycle), top + 2.0),
+                                    egui::vec2(CELL - 1.0, ROW - 4.0),
+                                );
+                                let color = stage_color(stage);
+                                painter.rect_filled(
+                                    cell,
+                                    1.0,
+                                    if v.squashed {
+                                        color.gamma_multiply(0.3)
+                                    } else {
+                                        color
+                                    },
+                                );
+                            });
+                        });
+
+                    let Some(pos) = response.hover_pos() else {
+                        return;
+                    };
+                    let row = ((pos.y - rect.top()) / ROW) as usize;
+                    let column = (pos.x - rect.left() - LABEL) / CELL;
+                    if row == 0 || column < 0.0 {
+                        return;
+                    }
+                    let cycle = column as u32 + 1;
+                    let Some(v) = t.instr_status_iter().nth(row - 1) else {
+                        return;
+                    };
+                    let Some(stage) = v.timing.stage(cycle) else {
+                        return;
+                    };
+                    let mut hover = format!("cycle {}: {}\n{}", cycle, v.instr, stage);
+                    if v.squashed {
+                        hover += " (squashed)";
+                    }
+                    hover += &format!("\nrob{}", v.tag);
+                    if let Some((class, i)) = v.station {
+                        hover += &format!(" in {}{}", class, i);
+                    }
+                    let sources = v
+                        .sources
+                        .iter()
+                        .flatten()
+                        .map(|v| format!("rob{}", v))
+                        .collect::<Vec<_>>();
+                    if !sources.is_empty() {
+                        hover += &format!("\nwaited on {}", sources.join(", "));
+                    }
+                    response.on_hover_text_at_pointer(hover);
+                });
+        });
+}
+
+const STAGES: [Stage; 7] = [
+    Stage::Issue,
+    Stage::Wait,
+    Stage::Execute,
+    Stage::CdbWait,
+    Stage::WriteResult,
+    Stage::Retire,
+    Stage::Commit,
+];
+
+fn stage_color(stage: Stage) -> Color32 {
+    match stage {
+        Stage::Issue => Color32::LIGHT_BLUE,
+        Stage::Wait => Color32::from_gray(110),
+        Stage::Execute => Color32::from_rgb(110, 255, 110),
+        Stage::CdbWait => Color32::LIGHT_RED,
+        Stage::WriteResult => Color32::YELLOW,
+        Stage::Retire => Color32::from_gray(60),
+        Stage::Commit => Color32::from_rgb(180, 130, 255),
+    }
+}
+
+/// Stations and registers in flight, with an arrow from every producer to
+/// the entries waiting on its tag. Results on the CDB this cycle are yellow.
+fn dataflow(ctx: &Context, t: &Tomasulo) {
+    const WIDTH: f32 = 120.0;
+    const HEIGHT: f32 = 34.0;
+    const GAP: f32 = 40.0;
+    Window::new("Dataflow")
+        .open(&mut true)
+        .title_bar(false)
+        .vscroll(false)
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.label(format!("Dataflow after cycle {}", t.cycle()));
+            let graph = Dataflow::new(t);
+            if graph.nodes.is_empty() {
+                ui.label("nothing in flight");
+                return;
+            }
+            let layers = graph.layers();
+            let mut rows = vec![0; layers.iter().max().unwrap() + 1];
+            let positions = layers
+                .iter()
+                .map(|&layer| {
+                    rows[layer] += 1;
+                    egui::vec2(
+                        layer as f32 * (WIDTH + GAP),
+                        (rows[layer] - 1) as f32 * (HEIGHT + GAP / 2.0),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let size = egui::vec2(
+                rows.len() as f32 * (WIDTH + GAP) - GAP,
+                *rows.iter().max().unwrap() as f32 * (HEIGHT + GAP / 2.0),
+            );
+            egui::ScrollArea::both().max_height(400.0).show(ui, |ui| {
+                let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                let painter = ui.painter_at(rect);
+                let node = |i: usize| {
+                    egui::Rect::from_min_size(rect.min + positions[i], egui::vec2(WIDTH, HEIGHT))
+                };
+                graph.edges.iter().for_each(|v| {
+                    let from = node(v.from).right_center();
+                    let to = node(v.to).left_center();
+                    let color = if v.delivered {
+                        Color32::YELLOW
+                    } else {
+                        ui.visuals().text_color()
+                    };
+                    painter.arrow(from, to - from, egui::Stroke::new(1.0, color));
+                });
+                graph.nodes.iter().enumerate().for_each(|(i, v)| {
+                    let (fill, stroke, text) = match v {
+                        Node::Station { class, .. } => (
+                            ui.visuals().extreme_bg_color,
+                            color(*class),
+                            ui.visuals().text_color(),
+                        ),
+                        Node::Broadcast { .. } => {
+                            (Color32::YELLOW, Color32::YELLOW, Color32::BLACK)
+                        }
+                        Node::Reg { .. } => (
+                            ui.visuals().faint_bg_color,
+                            ui.visuals().weak_text_color(),
+                            ui.visuals().text_color(),
+                        ),
+                    };
+                    painter.rect(node(i), 4.0, fill, egui::Stroke::new(1.5, stroke));
+                    painter.text(
+                        node(i).center(),
+                        egui::Align2::CENTER_CENTER,
+                        v.to_string(),
+                        egui::FontId::monospace(11.0),
+                        text,
+                    );
+                });
+            });
+        });
+}
+
+fn stats(ctx: &Context, stats: &Stats, config: &MachineConfig) {
+    Window::new("Statistics")
+        .open(&mut true)
+        .title_bar(false)
+        .vscroll(true)
+        .resizable(true)
+        .show(ctx, |ui| {
+            let summary = stats.summary(config);
+            ui.label("Statistics");
+            ui.label(format!(
+                "cycles {}  committed {}  IPC {:.3}",
+                summary.cycles, summary.committed, summary.ipc
+            ));
+            ui.label(format!(
+                "CDB busy {:.1}%  conflicts {}",
+                summary.cdb_utilization * 100.0,
+                summary.cdb_conflicts
+            ));
+
+            ui.push_id("classes", |ui| {
+                let table = TableBuilder::new(ui)
+                    .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                    .columns(Column::auto(), 4)
+                    .min_scrolled_height(0.0);
+                table
+                    .header(20.0, |mut header| {
+                        ["Class", "Occupancy", "Units busy", "Avg wait"]
+                            .iter()
+                            .for_each(|v| {
+                                header.col(|ui| {
+                                    ui.strong(*v);
+                                });
+                            });
+                    })
+                    .body(|mut body| {
+                        RsType::ALL.iter().for_each(|&class| {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(RichText::new(class.to_string()).color(color(class)));
+                                });
+                                row.col(|ui| {
+                                    ui.label(format!(
+                                        "{:.1}%",
+                                        summary.occupancy.get(class) * 100.0
+                                    ));
+                                });
+                                row.col(|ui| {
+                                    ui.label(format!(
+                                        "{:.1}%",
+                                        summary.utilization.get(class) * 100.0
+                                    ));
+                                });
+                                row.col(|ui| {
+                                    ui.label(format!("{:.2}", summary.wait.get(class)));
+                                });
+                            });
+                        });
+                    })
+            });
+
+            ui.label("Station occupancy");
+            occupancy(ui, stats, config);
+
+            ui.label(format!(
+                "Stalls: {}",
+                summary.stalls.values().sum::<usize>()
+            ));
+            ui.push_id("stalls", |ui| {
+                let table = TableBuilder::new(ui)
+                    .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                    .column(Column::auto())
+                    .column(Column::auto())
+                    .min_scrolled_height(0.0);
+                table
+                    .header(20.0, |mut header| {
+                        header.col(|ui| {
+                            ui.strong("Reason");
+                        });
+                        header.col(|ui| {
+                            ui.strong("Cycles");
+                        });
+                    })
+                    .body(|mut body| {
+                        summary.stalls.iter().for_each(|(reason, n)| {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(reason.to_string());
+                                });
+                                row.col(|ui| {
+                                    ui.label(n.to_string());
+                                });
+                            });
+                        });
+                    })
+            });
+        });
+}
+
+/// Busy stations of each class as a fraction of the class, cycle by cycle.
+fn occupancy(ui: &mut Ui, stats: &Stats, config: &MachineConfig) {
+    let size = egui::vec2(ui.available_width().max(200.0), 80.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+    let last = stats.samples.len().saturating_sub(1).max(1) as f32;
+    RsType::ALL.iter().for_each(|&class| {
+        let stations = (*config.stations.get(class)).max(1) as f32;
+        let points = stats
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                egui::pos2(
+                    rect.left() + rect.width() * i as f32 / last,
+                    rect.bottom() - rect.height() * *v.stations.get(class) as f32 / stations,
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5, color(class)),
+        ));
+    });
+}
+
+fn color(class: RsType) -> Color32 {
+    match class {
+        RsType::Add => Color32::LIGHT_BLUE,
+        RsType::Mul => Color32::LIGHT_RED,
+        RsType::Load => Color32::LIGHT_GREEN,
+        RsType::Store => Color32::YELLOW,
+    }
+}
+
 fn display(body: &mut TableBody<'_>, rows: &[Slot], label: String) {
     rows.iter().enumerate().for_each(|(i, v)| {
         body.row(18.0, |mut row| {
@@ -376,7 +1319,18 @@ fn display(body: &mut TableBody<'_>, rows: &[Slot], label: String) {
                 ui.label(v.busy.to_string());
             });
             row.col(|ui| {
-                ui.label(v.time.to_string());
+                if v.waiting_cdb {
+                    ui.label(RichText::new("wait CDB").color(Color32::YELLOW));
+                } else {
+                    ui.label(v.time.to_string());
+                }
+            });
+            row.col(|ui| {
+                if let Some(unit) = v.unit {
+                    ui.label(format!("{}{}", label, unit));
+                } else {
+                    ui.label("");
+                }
             });
             row.col(|ui| {
                 if let Some(addr) = v.addr {
@@ -408,14 +1362,14 @@ fn display(body: &mut TableBody<'_>, rows: &[Slot], label: String) {
             });
             row.col(|ui| {
                 if let Some(qj) = v.qj {
-                    ui.label(format!("{}{}", qj.0, qj.1));
+                    ui.label(format!("rob{}", qj));
                 } else {
                     ui.label("");
                 }
             });
             row.col(|ui| {
                 if let Some(qk) = v.qk {
-                    ui.label(format!("{}{}", qk.0, qk.1));
+                    ui.label(format!("rob{}", qk));
                 } else {
                     ui.label("");
                 }